@@ -0,0 +1,220 @@
+//! Encrypted bundles of a workspace, for sharing it with teammates.
+//!
+//! A bundle contains `Goki.toml` and every deployer keypair in `deployers/`, including
+//! archived and encrypted ones, sealed with a password like [Keystore]s are.
+
+use anyhow::{format_err, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::read_keypair_file, signer::Signer};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    keystore::{write_private_file, Keystore, Sealed, KEYSTORE_EXTENSION},
+    workspace::Workspace,
+};
+
+/// Identifies bundle files.
+pub const BUNDLE_FORMAT: &str = "goki-bundle";
+
+/// Environment variable the password of bundles is read from, if set.
+pub const PASSWORD_ENV: &str = "GOKI_BUNDLE_PASSWORD";
+
+const BUNDLE_VERSION: u32 = 1;
+
+/// A bundle file: the [Bundle], encrypted.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleFile {
+    format: String,
+    version: u32,
+    #[serde(flatten)]
+    sealed: Sealed,
+}
+
+/// A keypair file in a bundle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BundledKeypair {
+    pub pubkey: String,
+    /// Whether the keypair is itself encrypted, in which case it keeps its own password.
+    pub encrypted: bool,
+    /// Contents of the keypair file.
+    pub contents: String,
+}
+
+/// The contents of a bundle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    /// Unix timestamp of when the bundle was created.
+    pub created_at: u64,
+    /// Version of goki that created the bundle.
+    pub goki_version: String,
+    /// Contents of `Goki.toml`.
+    pub config: String,
+    /// Keypairs by their path relative to `deployers/`, with `/` as the separator.
+    pub deployers: BTreeMap<String, BundledKeypair>,
+}
+
+/// Whether a keypair file is encrypted, going by its name.
+pub fn is_encrypted_keypair(path: &Path) -> bool {
+    path.to_string_lossy()
+        .ends_with(&format!(".{}", KEYSTORE_EXTENSION))
+}
+
+/// Reads the public key of a keypair file, which may be encrypted.
+pub fn read_keypair_pubkey(path: &Path) -> Result<Pubkey> {
+    if is_encrypted_keypair(path) {
+        Keystore::read(path)?.pubkey()
+    } else {
+        Ok(read_keypair_file(path)
+            .map_err(|_| format_err!("could not read keypair {}", path.display()))?
+            .pubkey())
+    }
+}
+
+/// Collects the keypair files in `dir` and its subdirectories.
+fn collect_keypairs(
+    dir: &Path,
+    prefix: &str,
+    deployers: &mut BTreeMap<String, BundledKeypair>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_dir() {
+            collect_keypairs(&path, &format!("{}{}/", prefix, name), deployers)?;
+        } else if name.ends_with(".json") {
+            deployers.insert(
+                format!("{}{}", prefix, name),
+                BundledKeypair {
+                    pubkey: read_keypair_pubkey(&path)?.to_string(),
+                    encrypted: is_encrypted_keypair(&path),
+                    contents: fs::read_to_string(&path)?,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+impl Bundle {
+    /// Collects the config and deployers of a workspace.
+    pub fn collect(workspace: &Workspace) -> Result<Bundle> {
+        let mut deployers = BTreeMap::new();
+        let deployer_dir = workspace.deployer_dir();
+        if deployer_dir.exists() {
+            collect_keypairs(&deployer_dir, "", &mut deployers)?;
+        }
+        Ok(Bundle {
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            goki_version: env!("CARGO_PKG_VERSION").to_string(),
+            config: fs::read_to_string(&workspace.cfg_path)?,
+            deployers,
+        })
+    }
+
+    /// The path of a bundled keypair in the deployer directory `deployer_dir`.
+    ///
+    /// Fails if the path would be outside of the directory.
+    pub fn deployer_path(deployer_dir: &Path, name: &str) -> Result<PathBuf> {
+        let mut path = deployer_dir.to_path_buf();
+        for segment in name.split('/') {
+            if segment.is_empty() || segment == "." || segment == ".." || segment.contains('\\') {
+                return Err(format_err!("invalid keypair path {} in bundle", name));
+            }
+            path.push(segment);
+        }
+        Ok(path)
+    }
+
+    /// Encrypts the bundle with a password and writes it to `path`.
+    pub fn write(&self, path: &Path, password: &str) -> Result<()> {
+        let file = BundleFile {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            sealed: Sealed::seal(
+                &serde_json::to_vec(self)?,
+                password,
+                BUNDLE_FORMAT.as_bytes(),
+            )?,
+        };
+        write_private_file(path, serde_json::to_string_pretty(&file)?.as_bytes())
+    }
+
+    /// Reads a bundle, prompting for its password with `read_password`.
+    pub fn read<F>(path: &Path, read_password: F) -> Result<Bundle>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format_err!("could not read {}: {}", path.display(), e))?;
+        let file: BundleFile = serde_json::from_str(&contents)
+            .ok()
+            .filter(|file: &BundleFile| file.format == BUNDLE_FORMAT)
+            .ok_or_else(|| format_err!("{} is not a goki bundle", path.display()))?;
+        if file.version != BUNDLE_VERSION {
+            return Err(format_err!(
+                "unsupported bundle version {}; please upgrade goki",
+                file.version
+            ));
+        }
+        let plaintext = file
+            .sealed
+            .open(&read_password()?, BUNDLE_FORMAT.as_bytes())
+            .map_err(|e| format_err!("could not open {}: {}", path.display(), e))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_deployer_paths() {
+        let dir = Path::new("/workspace/deployers");
+        assert_eq!(
+            Bundle::deployer_path(dir, "devnet.json").unwrap(),
+            dir.join("devnet.json")
+        );
+        assert_eq!(
+            Bundle::deployer_path(dir, "archive/devnet-1.enc.json").unwrap(),
+            dir.join("archive").join("devnet-1.enc.json")
+        );
+    }
+
+    #[test]
+    fn rejects_deployer_paths_outside_of_the_directory() {
+        let dir = Path::new("/workspace/deployers");
+        for name in [
+            "../Goki.toml",
+            "archive/../../Goki.toml",
+            "..",
+            "./devnet.json",
+            "/etc/passwd",
+            "archive//devnet.json",
+            "archive/",
+            "",
+            r"..\Goki.toml",
+        ] {
+            assert!(
+                Bundle::deployer_path(dir, name).is_err(),
+                "{} should be rejected",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn detects_encrypted_keypairs_by_name() {
+        assert!(is_encrypted_keypair(Path::new("deployers/devnet.enc.json")));
+        assert!(!is_encrypted_keypair(Path::new("deployers/devnet.json")));
+    }
+}
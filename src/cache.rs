@@ -0,0 +1,297 @@
+//! Content-addressed cache of fetched program binaries.
+
+use anyhow::{format_err, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{copy, Seek, SeekFrom},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tempfile::NamedTempFile;
+
+use crate::{utils::sha256_digest, workspace::Workspace};
+
+/// A program binary stored in the cache.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CacheEntry {
+    /// The location the binary was fetched from.
+    pub location: String,
+    /// SHA256 of the binary, as hex.
+    pub sha256: String,
+    /// Size of the binary in bytes.
+    pub size: u64,
+    /// When the binary was fetched, in seconds since the Unix epoch.
+    pub fetched_at: u64,
+}
+
+impl CacheEntry {
+    /// How long ago the binary was fetched.
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.fetched_at))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct CacheIndex {
+    entries: Vec<CacheEntry>,
+}
+
+/// Cache of program binaries, stored as `<sha256>.so` files.
+pub struct ArtifactCache {
+    dir: PathBuf,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl ArtifactCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join("index.json")
+    }
+
+    /// Path of the cached binary with the given checksum.
+    pub fn artifact_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(format!("{}.so", sha256))
+    }
+
+    fn load_index(&self) -> Result<CacheIndex> {
+        let index_path = self.index_path();
+        if !index_path.exists() {
+            return Ok(CacheIndex::default());
+        }
+        let contents = fs::read_to_string(&index_path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format_err!("invalid cache index {}: {}", index_path.display(), e))
+    }
+
+    fn save_index(&self, index: &CacheIndex) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.index_path(), serde_json::to_string_pretty(index)?)?;
+        Ok(())
+    }
+
+    /// Lists all entries in the cache.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        Ok(self.load_index()?.entries)
+    }
+
+    /// Finds a cached binary.
+    ///
+    /// If a checksum is provided, the binary is looked up by its checksum. Otherwise,
+    /// the most recent binary fetched from `location` is used.
+    pub fn get(&self, location: &str, sha256: Option<&str>) -> Result<Option<PathBuf>> {
+        let sha256 = match sha256 {
+            Some(sha256) => sha256.to_string(),
+            None => match self
+                .entries()?
+                .into_iter()
+                .filter(|entry| entry.location == location)
+                .max_by_key(|entry| entry.fetched_at)
+            {
+                Some(entry) => entry.sha256,
+                None => return Ok(None),
+            },
+        };
+
+        let path = self.artifact_path(&sha256);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let (_, digest) = sha256_digest(&mut File::open(&path)?)?;
+        if digest != sha256 {
+            println!("Ignoring corrupted cache entry {}", path.display());
+            return Ok(None);
+        }
+        Ok(Some(path))
+    }
+
+    /// Adds the contents of `program_file` to the cache.
+    pub fn insert(&self, location: &str, program_file: &mut File) -> Result<CacheEntry> {
+        fs::create_dir_all(&self.dir)?;
+
+        program_file.seek(SeekFrom::Start(0))?;
+        let (size, sha256) = sha256_digest(program_file)?;
+        let artifact_path = self.artifact_path(&sha256);
+        if !artifact_path.exists() {
+            program_file.seek(SeekFrom::Start(0))?;
+            let mut tmp = NamedTempFile::new_in(&self.dir)?;
+            copy(program_file, &mut tmp)?;
+            tmp.persist(&artifact_path)?;
+        }
+        program_file.seek(SeekFrom::End(0))?;
+
+        let entry = CacheEntry {
+            location: location.to_string(),
+            sha256,
+            size,
+            fetched_at: now(),
+        };
+        let mut index = self.load_index()?;
+        index
+            .entries
+            .retain(|e| !(e.location == entry.location && e.sha256 == entry.sha256));
+        index.entries.push(entry.clone());
+        self.save_index(&index)?;
+        Ok(entry)
+    }
+
+    /// Removes entries older than `max_age`, entries whose binary is missing,
+    /// and binaries that are no longer referenced by any entry.
+    ///
+    /// Returns the paths of the removed binaries.
+    pub fn prune(&self, max_age: Option<Duration>) -> Result<Vec<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut index = self.load_index()?;
+        index.entries.retain(|entry| {
+            self.artifact_path(&entry.sha256).exists()
+                && !matches!(max_age, Some(max_age) if entry.age() > max_age)
+        });
+
+        let mut removed = vec![];
+        for file in fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("so") {
+                continue;
+            }
+            let referenced = index
+                .entries
+                .iter()
+                .any(|entry| self.artifact_path(&entry.sha256) == path);
+            if !referenced {
+                fs::remove_file(&path)?;
+                removed.push(path);
+            }
+        }
+
+        self.save_index(&index)?;
+        Ok(removed)
+    }
+
+    /// Removes everything from the cache.
+    pub fn clear(&self) -> Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+impl Workspace {
+    pub fn cache_dir(&self) -> PathBuf {
+        self.path.join("cache/")
+    }
+
+    /// The [ArtifactCache] of this workspace.
+    pub fn artifact_cache(&self) -> ArtifactCache {
+        ArtifactCache::new(self.cache_dir())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn program_file(contents: &[u8]) -> File {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn gets_binaries_by_location_and_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().join("cache"));
+        assert_eq!(cache.get("gh:a:b/c@1.0.0", None).unwrap(), None);
+
+        let old = cache
+            .insert("gh:a:b/c@1.0.0", &mut program_file(b"old"))
+            .unwrap();
+        let mut index = cache.load_index().unwrap();
+        index.entries[0].fetched_at -= 10;
+        cache.save_index(&index).unwrap();
+        let new = cache
+            .insert("gh:a:b/c@1.0.0", &mut program_file(b"new"))
+            .unwrap();
+        assert_eq!(new.size, 3);
+        assert_eq!(cache.entries().unwrap().len(), 2);
+
+        // Without a checksum, the binary fetched most recently is used.
+        assert_eq!(
+            cache.get("gh:a:b/c@1.0.0", None).unwrap(),
+            Some(cache.artifact_path(&new.sha256))
+        );
+        // With one, any location that fetched the binary will do.
+        assert_eq!(
+            cache.get("./old.so", Some(&old.sha256)).unwrap(),
+            Some(cache.artifact_path(&old.sha256))
+        );
+        assert_eq!(cache.get("gh:a:b/c@2.0.0", None).unwrap(), None);
+
+        // Inserting the same binary again only refreshes its entry.
+        cache
+            .insert("gh:a:b/c@1.0.0", &mut program_file(b"old"))
+            .unwrap();
+        assert_eq!(cache.entries().unwrap().len(), 2);
+        assert_eq!(
+            cache.get("gh:a:b/c@1.0.0", None).unwrap(),
+            Some(cache.artifact_path(&old.sha256))
+        );
+    }
+
+    #[test]
+    fn ignores_corrupted_binaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().to_path_buf());
+        let entry = cache.insert("./p.so", &mut program_file(b"p")).unwrap();
+        fs::write(cache.artifact_path(&entry.sha256), b"corrupted").unwrap();
+        assert_eq!(cache.get("./p.so", None).unwrap(), None);
+        assert_eq!(cache.get("./p.so", Some(&entry.sha256)).unwrap(), None);
+    }
+
+    #[test]
+    fn prunes_old_entries_and_unreferenced_binaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().to_path_buf());
+        let old = cache.insert("./old.so", &mut program_file(b"old")).unwrap();
+        let new = cache.insert("./new.so", &mut program_file(b"new")).unwrap();
+        let mut index = cache.load_index().unwrap();
+        index.entries[0].fetched_at -= 3_600;
+        cache.save_index(&index).unwrap();
+        let stray = cache.artifact_path("stray");
+        fs::write(&stray, b"stray").unwrap();
+
+        assert_eq!(cache.prune(None).unwrap(), vec![stray]);
+        assert_eq!(cache.entries().unwrap().len(), 2);
+
+        let removed = cache.prune(Some(Duration::from_secs(60))).unwrap();
+        assert_eq!(removed, vec![cache.artifact_path(&old.sha256)]);
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].sha256, new.sha256);
+
+        // Entries whose binary was deleted are dropped.
+        fs::remove_file(cache.artifact_path(&new.sha256)).unwrap();
+        assert!(cache.prune(None).unwrap().is_empty());
+        assert!(cache.entries().unwrap().is_empty());
+
+        cache.clear().unwrap();
+        assert!(!cache.dir().exists());
+        assert!(cache.prune(None).unwrap().is_empty());
+    }
+}
@@ -1,41 +1,92 @@
 //! Goki entrypoint
 
-use anchor_client::Cluster;
 use anyhow::Result;
 use clap::Parser;
 use std::path::PathBuf;
 
-use crate::{subcommands, workspace::Workspace};
+use crate::{
+    cluster::ClusterRef, keystore, location::FetchOptions, program::ProgramArgs, subcommands,
+    workspace::Workspace,
+};
 
 const LOCATION_HELP: &str =
     "The location of the Solana program binary. This can be in one of the following formats:
 
 - path, for example `./path/to/program.so`
 - URL, for example `https://github.com/GokiProtocol/goki/releases/download/v0.5.2/smart_wallet.so`
-- GitHub artifact, for example `gh:smart_wallet:GokiProtocol/goki@0.5.2`
+- GitHub artifact, for example `gh:smart_wallet:GokiProtocol/goki@0.5.2`. The version may also be `latest` or a semver range such as `^0.5`
 - Solana Program Registry artifact, for example `spr:QuarryProtocol/quarry_mine`
+- Anchor program in the nearest Anchor workspace, for example `anchor:smart_wallet`
+- Crate in the current Cargo workspace, built with `cargo build-sbf`, for example `cargo:smart-wallet`
+- IPFS content, for example `ipfs:<cid>`, or Arweave transaction, for example `ar:<transaction id>`
+- On-chain program or buffer account, for example `chain:mainnet:GokivDYuQXPZCWRkwMhdH2h91KpDQXBEmpgBgs55bnpH`
+
+Any location may be pinned to a checksum by appending `#sha256=<hex>`.
 ";
 
+/// Output format of commands that support machine-readable output.
+#[derive(Clone, Copy, Debug, clap::ArgEnum, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Clone, Debug, clap::Subcommand, PartialEq, Eq)]
 pub enum SubCommand {
     /// Initializes a new Goki workspace.
-    Init,
+    ///
+    /// Anything that already exists is kept, so this may be re-run to fill in what is missing.
+    Init {
+        /// Clusters to create deployers for, separated by commas.
+        /// Defaults to devnet, testnet, mainnet and the clusters in `Goki.toml`.
+        #[clap(long)]
+        #[clap(use_value_delimiter = true)]
+        clusters: Vec<ClusterRef>,
+
+        /// Skips requesting airdrops for the new deployers on devnet and testnet.
+        #[clap(long)]
+        no_airdrop: bool,
+
+        /// Copies deployer keypairs from an existing workspace directory, such as another project's `.goki/`.
+        #[clap(long)]
+        from_existing: Option<PathBuf>,
+
+        /// Only creates `Goki.toml`.
+        #[clap(long)]
+        #[clap(conflicts_with_all = &["clusters", "no-airdrop", "from-existing"])]
+        config_only: bool,
+    },
     /// Shows information about the Goki workspace.
-    Show,
+    ///
+    /// For each cluster, shows the deployer and its balance, the buffers whose authority is the
+    /// deployer, and the configured upgrade authority.
+    Show {
+        /// Only shows these clusters. May be passed more than once.
+        #[clap(short, long)]
+        #[clap(multiple_occurrences = true)]
+        cluster: Vec<ClusterRef>,
+
+        /// Output format.
+        #[clap(long, arg_enum)]
+        #[clap(default_value = "text")]
+        output: OutputFormat,
+    },
     /// Shows the balance of the deployer.
     Balance {
         /// Cluster.
         #[clap(short, long)]
         #[clap(default_value = "devnet")]
-        cluster: Cluster,
+        cluster: ClusterRef,
     },
     /// Requests an airdrop of SOL from the Solana network.
     Airdrop {
-        /// Cluster to request from.
+        /// Cluster to request from: devnet, testnet, localnet or a named cluster.
+        ///
+        /// Named clusters defined in `[clusters]` of `Goki.toml`, such as private test validators,
+        /// may run a faucet too, so any cluster other than mainnet is accepted.
         #[clap(short, long)]
-        #[clap(possible_value("devnet"), possible_value("testnet"))]
         #[clap(default_value = "devnet")]
-        cluster: Cluster,
+        cluster: ClusterRef,
 
         /// Airdrop request amount in SOL.
         #[clap(default_value = "1")]
@@ -56,7 +107,7 @@ pub enum SubCommand {
         /// Cluster to transfer tokens on.
         #[clap(short, long)]
         #[clap(default_value = "devnet")]
-        cluster: Cluster,
+        cluster: ClusterRef,
 
         /// Who to transfer from. Defaults to the upgrade authority ("upgrader").
         #[clap(short, long)]
@@ -78,18 +129,24 @@ pub enum SubCommand {
         /// Cluster to deploy to.
         #[clap(short, long)]
         #[clap(default_value = "devnet")]
-        cluster: Cluster,
+        cluster: ClusterRef,
+
+        #[clap(flatten)]
+        program: ProgramArgs,
 
         #[clap(short, long)]
         #[clap(help = LOCATION_HELP)]
         #[clap(next_line_help = true)]
-        location: String,
+        location: Option<String>,
 
         /// The program being upgraded.
         ///
         /// The buffer authority will be set to the program's current upgrade authority.
         #[clap(short, long)]
-        program_id: String,
+        program_id: Option<String>,
+
+        #[clap(flatten)]
+        fetch: FetchOptions,
     },
 
     /// Deploys a program for the first time.
@@ -97,27 +154,33 @@ pub enum SubCommand {
         /// Cluster to deploy to.
         #[clap(short, long)]
         #[clap(default_value = "devnet")]
-        cluster: Cluster,
+        cluster: ClusterRef,
 
         /// The public key of the upgrade authority. If not provided, the deployer key will be used if not on mainnet.
         #[clap(short, long)]
         upgrade_authority: Option<String>,
 
+        #[clap(flatten)]
+        program: ProgramArgs,
+
         #[clap(short, long)]
         #[clap(help = LOCATION_HELP)]
         #[clap(next_line_help = true)]
-        location: String,
+        location: Option<String>,
 
         /// The path to the keypair of the program being deployed.
         #[clap(short, long)]
         program_kp: PathBuf,
+
+        #[clap(flatten)]
+        fetch: FetchOptions,
     },
     /// Upgrades a program using a local signer.
     UpgradeLocal {
         /// Cluster to deploy to.
         #[clap(short, long)]
         #[clap(default_value = "devnet")]
-        cluster: Cluster,
+        cluster: ClusterRef,
 
         /// The keypair of the upgrade authority.
         ///
@@ -125,13 +188,19 @@ pub enum SubCommand {
         #[clap(short, long)]
         upgrade_authority_keypair: Option<String>,
 
+        #[clap(flatten)]
+        program: ProgramArgs,
+
         /// The path to the Solana program bytecode. If a public key is provided, this will use an already uploaded program buffer.
         #[clap(short, long)]
-        location: String,
+        location: Option<String>,
 
         /// The program being upgraded.
         #[clap(short, long)]
-        program_id: String,
+        program_id: Option<String>,
+
+        #[clap(flatten)]
+        fetch: FetchOptions,
     },
     /// Pulls a binary from a location.
     Pull {
@@ -144,32 +213,299 @@ pub enum SubCommand {
         /// If not specified, the program binary will not be written.
         #[clap(short, long)]
         out: Option<PathBuf>,
+
+        /// Resumes a partial download into the existing output file instead of overwriting it.
+        #[clap(long)]
+        #[clap(requires = "out")]
+        resume: bool,
+
+        #[clap(flatten)]
+        fetch: FetchOptions,
+    },
+    /// Manages the cache of fetched program binaries.
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
+    /// Browses the Solana Program Registry.
+    Registry {
+        #[clap(subcommand)]
+        command: RegistryCommand,
+    },
+    /// Inspects program locations.
+    Location {
+        #[clap(subcommand)]
+        command: LocationCommand,
+    },
+    /// Inspects and edits the configuration.
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Manages deployer keypairs.
+    Keys {
+        #[clap(subcommand)]
+        command: KeysCommand,
+    },
+    /// Exports and imports workspaces, for sharing them with teammates.
+    Workspace {
+        #[clap(subcommand)]
+        command: WorkspaceCommand,
+    },
+}
+
+#[derive(Clone, Debug, clap::Subcommand, PartialEq, Eq)]
+pub enum CacheCommand {
+    /// Lists cached program binaries.
+    Ls,
+    /// Removes stale cache entries and unreferenced program binaries.
+    Prune {
+        /// Also removes programs fetched more than this many days ago.
+        #[clap(long)]
+        older_than: Option<u64>,
+    },
+    /// Removes all cached program binaries.
+    Clear,
+}
+
+#[derive(Clone, Debug, clap::Subcommand, PartialEq, Eq)]
+pub enum RegistryCommand {
+    /// Searches for artifacts by ID or name.
+    ///
+    /// Only local indexes can be searched, since remote indexes do not publish a listing of
+    /// their artifacts.
+    Search { query: String },
+    /// Shows the metadata of an artifact, for example `QuarryProtocol/quarry_mine@latest`.
+    Show { id: String },
+}
+
+#[derive(Clone, Debug, clap::Subcommand, PartialEq, Eq)]
+pub enum LocationCommand {
+    /// Parses a location and prints where it would be fetched from, without fetching it.
+    Parse {
+        #[clap(help = LOCATION_HELP)]
+        #[clap(next_line_help = true)]
+        location: String,
+    },
+}
+
+#[derive(Clone, Debug, clap::Subcommand, PartialEq, Eq)]
+pub enum ConfigCommand {
+    /// Prints a config value, for example `github.api_url`.
+    Get {
+        key: String,
+        /// Also prints where the value came from.
+        #[clap(long)]
+        show_origin: bool,
+    },
+    /// Sets a config value in `Goki.toml`.
+    Set {
+        key: String,
+        /// The value, parsed as TOML if possible and as a string otherwise.
+        value: String,
+        /// Sets the value in the global config instead.
+        #[clap(long)]
+        global: bool,
+    },
+    /// Lists all config values.
+    List {
+        /// Also prints where each value came from.
+        #[clap(long)]
+        show_origin: bool,
+    },
+    /// Migrates `Goki.toml` to the latest config format, preserving comments and ordering.
+    Migrate {
+        /// Migrates the global config instead.
+        #[clap(long)]
+        global: bool,
+        /// Prints the changes without writing them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Checks the config for problems, such as unknown keys, invalid URLs and missing keypairs.
+    ///
+    /// All problems are reported at once. Exits with an error if any are found.
+    Check,
+}
+
+#[derive(Clone, Debug, clap::Subcommand, PartialEq, Eq)]
+pub enum KeysCommand {
+    /// Creates a new deployer keypair for a cluster.
+    ///
+    /// The keypair is random by default. It may instead be derived from a new BIP39 mnemonic,
+    /// which is printed once for backup, or ground until its public key starts with a prefix.
+    New {
+        /// Cluster to create the deployer for.
+        #[clap(short, long)]
+        #[clap(default_value = "devnet")]
+        cluster: ClusterRef,
+
+        /// Derives the keypair from a new BIP39 mnemonic.
+        #[clap(long)]
+        #[clap(conflicts_with = "vanity")]
+        mnemonic: bool,
+
+        /// Number of words in the mnemonic: 12, 15, 18, 21 or 24. Defaults to 12.
+        #[clap(long)]
+        #[clap(requires = "mnemonic")]
+        word_count: Option<usize>,
+
+        /// Derivation path of the keypair. Defaults to `m/44'/501'/0'/0'`.
+        #[clap(long)]
+        #[clap(requires = "mnemonic")]
+        derivation_path: Option<String>,
+
+        /// Generates keypairs until one has a public key starting with this base58 prefix.
+        #[clap(long)]
+        vanity: Option<String>,
+
+        /// Matches the vanity prefix case-insensitively.
+        #[clap(long)]
+        #[clap(requires = "vanity")]
+        ignore_case: bool,
+
+        /// Number of threads to search for a vanity keypair on. Defaults to the number of CPUs.
+        #[clap(long)]
+        #[clap(requires = "vanity")]
+        threads: Option<usize>,
+
+        /// Replaces the existing deployer of the cluster, archiving it in `deployers/archive/`.
+        /// An encrypted deployer is replaced with an encrypted one.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Encrypts the deployer keypair of a cluster with a password.
+    ///
+    /// The password is read from `GOKI_KEYSTORE_PASSWORD` if it is set, and prompted for otherwise.
+    Encrypt {
+        /// Cluster of the deployer.
+        #[clap(short, long)]
+        #[clap(default_value = "devnet")]
+        cluster: ClusterRef,
+    },
+    /// Decrypts the deployer keypair of a cluster, storing it in plaintext.
+    Decrypt {
+        /// Cluster of the deployer.
+        #[clap(short, long)]
+        #[clap(default_value = "devnet")]
+        cluster: ClusterRef,
+    },
+    /// Replaces the deployer of a cluster with a new keypair.
+    ///
+    /// Buffers whose authority is the old deployer are handed over to the new one and the
+    /// old deployer's balance is transferred to it. The old keypair is archived in
+    /// `deployers/archive/`. Encrypted deployers are replaced with an encrypted deployer
+    /// whose password is read from `GOKI_KEYSTORE_NEW_PASSWORD` if it is set, and prompted
+    /// for otherwise.
+    Rotate {
+        /// Cluster of the deployer.
+        #[clap(short, long)]
+        #[clap(default_value = "devnet")]
+        cluster: ClusterRef,
+    },
+    /// Changes the password of an encrypted deployer keypair.
+    ///
+    /// The new password is read from `GOKI_KEYSTORE_NEW_PASSWORD` if it is set, and prompted for otherwise.
+    ChangePassword {
+        /// Cluster of the deployer.
+        #[clap(short, long)]
+        #[clap(default_value = "devnet")]
+        cluster: ClusterRef,
+    },
+}
+
+#[derive(Clone, Debug, clap::Subcommand, PartialEq, Eq)]
+pub enum WorkspaceCommand {
+    /// Exports `Goki.toml` and the deployer keypairs to a password-encrypted bundle.
+    ///
+    /// The password is read from `GOKI_BUNDLE_PASSWORD` if it is set, and prompted for otherwise.
+    Export {
+        /// Path to write the bundle to, for example `bundle.goki`.
+        #[clap(long)]
+        out: PathBuf,
+        /// Overwrites the bundle if it exists.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Imports a bundle created by `goki workspace export`.
+    ///
+    /// Keypairs that already exist with a different public key, and a different `Goki.toml`,
+    /// are conflicts, and nothing is imported unless `--force` is passed.
+    Import {
+        /// Path to the bundle.
+        bundle: PathBuf,
+        /// Replaces conflicting keypairs, archiving them in `deployers/archive/`, and `Goki.toml`.
+        #[clap(long)]
+        force: bool,
     },
 }
 
 #[derive(Clone, Debug, clap::Parser)]
 #[clap(about, version, author)]
 pub struct Opts {
-    /// Path to the workspace.
+    /// Path to the workspace. Defaults to `.goki/` next to `Goki.toml`.
     #[clap(short, long)]
-    #[clap(default_value = ".goki/")]
-    pub workspace_path: PathBuf,
+    pub workspace_path: Option<PathBuf>,
+    /// Overrides a config value, for example `--config github.api_url=https://github.example.com/api/v3`.
+    ///
+    /// This takes precedence over `Goki.toml`, the global config and `GOKI_*` environment variables.
+    #[clap(long = "config", value_name = "KEY=VALUE")]
+    #[clap(multiple_occurrences = true)]
+    pub config_overrides: Vec<String>,
     #[clap(subcommand)]
     pub command: SubCommand,
 }
 
 impl Opts {
     pub async fn run(&self) -> Result<()> {
-        if self.command.clone() == SubCommand::Init {
-            return subcommands::init::process(&self.workspace_path);
+        let workspace_path = self.workspace_path.as_deref();
+        match self.command.clone() {
+            SubCommand::Init {
+                clusters,
+                no_airdrop,
+                from_existing,
+                config_only,
+            } => {
+                return subcommands::init::process(
+                    workspace_path,
+                    &self.config_overrides,
+                    clusters,
+                    no_airdrop,
+                    from_existing.as_deref(),
+                    config_only,
+                );
+            }
+            SubCommand::Config { command } => {
+                return subcommands::config::process(
+                    workspace_path,
+                    &self.config_overrides,
+                    command,
+                );
+            }
+            SubCommand::Workspace { command } => {
+                return subcommands::workspace::process(
+                    workspace_path,
+                    &self.config_overrides,
+                    command,
+                );
+            }
+            _ => {}
         }
 
-        let workspace = Workspace::load(&self.workspace_path)?;
-        println!("Using workspace at {}", workspace.path.display());
+        let workspace = Workspace::load(workspace_path, &self.config_overrides)?;
+        if !matches!(
+            self.command,
+            SubCommand::Show {
+                output: OutputFormat::Json,
+                ..
+            }
+        ) {
+            println!("Using workspace at {}", workspace.path.display());
+        }
         match self.command.clone() {
-            SubCommand::Init => {}
-            SubCommand::Show => {
-                subcommands::show::process(&workspace)?;
+            SubCommand::Init { .. } | SubCommand::Config { .. } | SubCommand::Workspace { .. } => {}
+            SubCommand::Show { cluster, output } => {
+                subcommands::show::process(&workspace, cluster, output)?;
             }
             SubCommand::Airdrop {
                 cluster,
@@ -198,46 +534,73 @@ impl Opts {
             }
             SubCommand::UploadProgramBuffer {
                 cluster,
+                program,
                 location,
                 program_id,
+                fetch,
             } => {
-                subcommands::upload_program_buffer::process(
-                    &workspace, cluster, location, program_id,
-                )
-                .await?;
+                let program =
+                    workspace.resolve_program(&cluster, &program, program_id, location)?;
+                subcommands::upload_program_buffer::process(&workspace, cluster, &program, &fetch)
+                    .await?;
             }
             SubCommand::Deploy {
                 cluster,
                 upgrade_authority,
+                program,
                 location,
                 program_kp,
+                fetch,
             } => {
+                let program = workspace.resolve_program(&cluster, &program, None, location)?;
                 subcommands::deploy::process(
                     &workspace,
                     cluster,
                     upgrade_authority,
-                    location,
+                    &program,
                     &program_kp,
+                    &fetch,
                 )
                 .await?;
             }
             SubCommand::UpgradeLocal {
                 cluster,
                 upgrade_authority_keypair,
+                program,
                 location,
                 program_id,
+                fetch,
             } => {
+                let program =
+                    workspace.resolve_program(&cluster, &program, program_id, location)?;
                 subcommands::upgrade_local::process(
                     &workspace,
                     cluster,
                     upgrade_authority_keypair,
-                    location,
-                    program_id,
+                    &program,
+                    &fetch,
                 )
                 .await?;
             }
-            SubCommand::Pull { location, out } => {
-                subcommands::pull::process(&location, out).await?;
+            SubCommand::Pull {
+                location,
+                out,
+                resume,
+                fetch,
+            } => {
+                subcommands::pull::process(&workspace, &location, out, resume, &fetch).await?;
+            }
+            SubCommand::Cache { command } => {
+                subcommands::cache::process(&workspace, command)?;
+            }
+            SubCommand::Registry { command } => {
+                subcommands::registry::process(&workspace, command).await?;
+            }
+            SubCommand::Location { command } => {
+                subcommands::location::process(&workspace, command)?;
+            }
+            SubCommand::Keys { command } => {
+                subcommands::keys::process(&workspace, command)?;
             }
         };
         Ok(())
@@ -247,7 +610,14 @@ impl Opts {
 /// Runs the CLI.
 pub async fn run() -> Result<()> {
     let opts: Opts = Opts::parse();
-    opts.run().await?;
-
-    Ok(())
+    // Remove unlocked keypairs if the command is interrupted.
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            keystore::lock_all();
+            std::process::exit(130);
+        }
+    });
+    let result = opts.run().await;
+    keystore::lock_all();
+    result
 }
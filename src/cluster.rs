@@ -0,0 +1,305 @@
+//! Clusters that commands can be run against.
+
+use anchor_client::{
+    solana_client::{
+        client_error::Result as ClientResult,
+        rpc_client::{RpcClient, RpcClientConfig},
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+        rpc_request::{RpcError, RpcRequest, RpcResponseErrorData},
+        rpc_sender::{RpcSender, RpcTransportStats},
+    },
+    Cluster,
+};
+use anyhow::{format_err, Result};
+use colored::*;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use serde::Deserialize;
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_sdk::{bpf_loader_upgradeable, commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{config::ClusterConfig, workspace::Workspace};
+
+/// Clusters that deployers are always created for.
+pub const DEFAULT_CLUSTERS: [Cluster; 3] = [Cluster::Devnet, Cluster::Testnet, Cluster::Mainnet];
+
+/// A cluster: either one of the well-known Solana clusters, or one defined in
+/// `[clusters.<name>]` of `Goki.toml`.
+///
+/// Well-known clusters may also be redefined in `[clusters]`, for example to use
+/// a private RPC provider for `mainnet`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClusterRef {
+    Builtin(Cluster),
+    Named(String),
+}
+
+impl From<Cluster> for ClusterRef {
+    fn from(cluster: Cluster) -> Self {
+        ClusterRef::Builtin(cluster)
+    }
+}
+
+impl ClusterRef {
+    /// Whether this is mainnet.
+    pub fn is_mainnet(&self) -> bool {
+        *self == ClusterRef::Builtin(Cluster::Mainnet)
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+impl FromStr for ClusterRef {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if !is_valid_name(s) {
+            return Err(format_err!(
+                "invalid cluster name `{}`: names may only contain letters, digits, `_` and `-`",
+                s
+            ));
+        }
+        Ok(match Cluster::from_str(s) {
+            Ok(Cluster::Custom(_, _)) | Err(_) => ClusterRef::Named(s.to_string()),
+            Ok(cluster) => ClusterRef::Builtin(cluster),
+        })
+    }
+}
+
+impl fmt::Display for ClusterRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClusterRef::Builtin(cluster) => write!(f, "{}", cluster),
+            ClusterRef::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+}
+
+/// [RpcSender] that sends the headers configured for a cluster with every request.
+struct HeaderSender {
+    client: reqwest::blocking::Client,
+    url: String,
+    request_id: AtomicU64,
+    stats: RwLock<RpcTransportStats>,
+}
+
+impl HeaderSender {
+    fn new(url: &str, headers: &BTreeMap<String, String>) -> Result<Self> {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers.iter() {
+            header_map.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        let client = tokio::task::block_in_place(move || {
+            reqwest::blocking::Client::builder()
+                .default_headers(header_map)
+                .timeout(Duration::from_secs(30))
+                .build()
+        })?;
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            request_id: AtomicU64::new(0),
+            stats: RwLock::new(RpcTransportStats::default()),
+        })
+    }
+}
+
+impl RpcSender for HeaderSender {
+    fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let start = Instant::now();
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let request_json = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": request_id,
+            "method": request.to_string(),
+            "params": params,
+        })
+        .to_string();
+        let response = tokio::task::block_in_place(|| {
+            self.client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(request_json)
+                .send()
+                .and_then(|response| response.error_for_status())
+                .and_then(|response| response.json::<serde_json::Value>())
+        });
+        {
+            let mut stats = self.stats.write().unwrap();
+            stats.request_count += 1;
+            stats.elapsed_time += start.elapsed();
+        }
+
+        let mut json = response?;
+        if json["error"].is_object() {
+            return match serde_json::from_value::<RpcErrorObject>(json["error"].clone()) {
+                Ok(error) => Err(RpcError::RpcResponseError {
+                    code: error.code,
+                    message: error.message,
+                    data: RpcResponseErrorData::Empty,
+                }
+                .into()),
+                Err(err) => Err(RpcError::RpcRequestError(format!(
+                    "failed to deserialize RPC error response: {} [{}]",
+                    json["error"], err
+                ))
+                .into()),
+            };
+        }
+        Ok(json["result"].take())
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.stats.read().unwrap().clone()
+    }
+}
+
+impl Workspace {
+    /// The configuration of a cluster, with its URL resolved.
+    ///
+    /// URLs in `[clusters]` take precedence over the default URLs of the well-known clusters.
+    pub fn get_cluster(&self, cluster: &ClusterRef) -> Result<ClusterConfig> {
+        let cluster_cfg = self
+            .cfg
+            .clusters
+            .get(&cluster.to_string())
+            .cloned()
+            .unwrap_or_default();
+        Ok(ClusterConfig {
+            url: Some(self.get_cluster_url(cluster)?.to_string()),
+            ..cluster_cfg
+        })
+    }
+
+    /// All clusters of the workspace: the default clusters, followed by those in `[clusters]`.
+    pub fn clusters(&self) -> Result<Vec<ClusterRef>> {
+        let mut clusters: Vec<ClusterRef> = DEFAULT_CLUSTERS
+            .iter()
+            .cloned()
+            .map(ClusterRef::from)
+            .collect();
+        for name in self.cfg.clusters.keys() {
+            let cluster: ClusterRef = name.parse()?;
+            if !clusters.contains(&cluster) {
+                clusters.push(cluster);
+            }
+        }
+        Ok(clusters)
+    }
+
+    /// Arguments that point the `solana` CLI at a cluster.
+    pub fn cluster_args(&self, cluster: &ClusterRef) -> Result<Vec<String>> {
+        let cluster_cfg = self.get_cluster(cluster)?;
+        if !cluster_cfg.headers.is_empty() {
+            eprintln!(
+                "{} the solana CLI does not support custom headers, so the headers of cluster {} are not sent",
+                "Warning:".yellow(),
+                cluster
+            );
+        }
+        let mut args = vec![
+            "--url".to_string(),
+            self.get_cluster_url(cluster)?.to_string(),
+        ];
+        if let Some(ws_url) = cluster_cfg.ws_url {
+            args.extend(["--ws".to_string(), ws_url]);
+        }
+        if let Some(commitment) = cluster_cfg.commitment {
+            args.extend(["--commitment".to_string(), commitment.to_string()]);
+        }
+        Ok(args)
+    }
+
+    /// The commitment configured for a cluster.
+    pub fn commitment(&self, cluster: &ClusterRef) -> Result<CommitmentConfig> {
+        Ok(CommitmentConfig {
+            commitment: self.get_cluster(cluster)?.commitment.unwrap_or_default(),
+        })
+    }
+
+    /// An [RpcClient] for a cluster, which sends the configured headers.
+    ///
+    /// Clients with custom headers use the default commitment, since `solana_client` does
+    /// not allow setting it on a custom sender, so pass [Workspace::commitment] explicitly
+    /// to `*_with_commitment` methods.
+    pub fn rpc_client(&self, cluster: &ClusterRef) -> Result<RpcClient> {
+        let cluster_cfg = self.get_cluster(cluster)?;
+        let url = self.get_cluster_url(cluster)?;
+        if cluster_cfg.headers.is_empty() {
+            return Ok(RpcClient::new_with_commitment(
+                url.to_string(),
+                self.commitment(cluster)?,
+            ));
+        }
+        Ok(RpcClient::new_sender(
+            HeaderSender::new(url, &cluster_cfg.headers)?,
+            RpcClientConfig::default(),
+        ))
+    }
+
+    /// Buffer accounts of the upgradeable BPF loader whose authority is `authority`,
+    /// with their balances in lamports.
+    pub fn get_buffers(
+        &self,
+        cluster: &ClusterRef,
+        authority: &Pubkey,
+    ) -> Result<Vec<(Pubkey, u64)>> {
+        // A buffer starts with the `Buffer` variant of `UpgradeableLoaderState`, a `u32`,
+        // followed by its authority as an `Option<Pubkey>`.
+        let mut prefix = 1u32.to_le_bytes().to_vec();
+        prefix.push(1);
+        prefix.extend_from_slice(authority.as_ref());
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp {
+                offset: 0,
+                bytes: MemcmpEncodedBytes::Base58(solana_sdk::bs58::encode(prefix).into_string()),
+                encoding: None,
+            })]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                data_slice: Some(UiDataSliceConfig {
+                    offset: 0,
+                    length: 0,
+                }),
+                commitment: Some(self.commitment(cluster)?),
+            },
+            with_context: None,
+        };
+        let accounts = self
+            .rpc_client(cluster)?
+            .get_program_accounts_with_config(&bpf_loader_upgradeable::id(), config)
+            .map_err(|e| format_err!("could not fetch buffers of {}: {}", authority, e))?;
+        Ok(accounts
+            .into_iter()
+            .map(|(address, account)| (address, account.lamports))
+            .collect())
+    }
+}
@@ -0,0 +1,64 @@
+//! Edits config files in place, preserving their formatting and comments.
+
+use anyhow::{format_err, Result};
+use std::{fs, path::Path};
+use toml_edit::{Document, Item, Table};
+
+use super::{migrate, validate};
+
+/// Reads a config file as an editable [Document]. Missing files are empty.
+pub fn read_document(path: &Path) -> Result<Document> {
+    if !path.exists() {
+        return Ok(Document::new());
+    }
+    fs::read_to_string(path)?
+        .parse::<Document>()
+        .map_err(|e| format_err!("could not parse {}: {}", path.display(), e))
+}
+
+/// Checks that `doc` is a valid config, then writes it to `path`.
+pub fn write_document(path: &Path, doc: &Document) -> Result<()> {
+    let contents = doc.to_string();
+    let migrated = migrate::migrate_source(&contents)?;
+    let (_, diagnostics) = validate::check_source(
+        &path.display().to_string(),
+        migrated.as_deref().unwrap_or(&contents),
+    );
+    if let Some(diagnostics) = validate::format_diagnostics(&diagnostics) {
+        return Err(format_err!(
+            "refusing to write invalid config to {}:\n{}",
+            path.display(),
+            diagnostics
+        ));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Sets the value at `key` in `doc`, creating tables as needed.
+///
+/// The value is parsed as TOML if possible and is a string otherwise.
+pub fn set_value(doc: &mut Document, key: &[String], raw_value: &str) -> Result<()> {
+    let (last, parents) = key
+        .split_last()
+        .ok_or_else(|| format_err!("config key must not be empty"))?;
+    let mut table: &mut Table = doc.as_table_mut();
+    for segment in parents {
+        let item = table.entry(segment).or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        });
+        table = item
+            .as_table_mut()
+            .ok_or_else(|| format_err!("`{}` is not a table", segment))?;
+    }
+    let value = raw_value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| raw_value.into());
+    table.insert(last, toml_edit::value(value));
+    Ok(())
+}
@@ -0,0 +1,265 @@
+//! Interpolation of environment variables in config values.
+//!
+//! String values in config files may contain `${VAR}`, which is replaced with the value of
+//! the environment variable `VAR`, or `${VAR:-default}`, which falls back to `default` if
+//! `VAR` is unset or empty. `$${` is a literal `${`.
+//!
+//! Values substituted from the environment that may be secrets are redacted from commands
+//! and links that are printed. Variables whose names look like secrets, such as `RPC_API_KEY`,
+//! always are; well-known variables such as `HOME` and values shorter than
+//! [MIN_SECRET_LEN] never are.
+
+use lazy_static::lazy_static;
+use std::{collections::BTreeSet, sync::RwLock};
+use thiserror::Error;
+use toml::{value::Table, Value};
+
+use super::validate::Problem;
+
+/// Replacement of secrets in redacted text.
+pub const REDACTED: &str = "****";
+
+/// Values of environment variables shorter than this, such as port numbers, are not
+/// redacted unless the name of the variable looks like a secret.
+pub const MIN_SECRET_LEN: usize = 8;
+
+/// Parts of names of environment variables that hold secrets.
+const SECRET_NAME_PARTS: [&str; 6] = ["KEY", "TOKEN", "SECRET", "PASS", "AUTH", "CREDENTIAL"];
+
+/// Environment variables that never hold secrets.
+const NON_SECRET_NAMES: [&str; 9] = [
+    "HOME", "USER", "USERNAME", "LOGNAME", "PWD", "SHELL", "TMPDIR", "TMP", "TEMP",
+];
+
+lazy_static! {
+    static ref SECRETS: RwLock<BTreeSet<String>> = RwLock::new(BTreeSet::new());
+}
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum InterpolationError {
+    #[error("environment variable {0} is not set")]
+    Undefined(String),
+    #[error("invalid environment variable name `{0}`")]
+    InvalidName(String),
+    #[error("unterminated `${{` in `{0}`")]
+    Unterminated(String),
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether the value of the environment variable `name` should be redacted.
+fn is_secret(name: &str, value: &str) -> bool {
+    if value.is_empty() {
+        return false;
+    }
+    let upper = name.to_ascii_uppercase();
+    if SECRET_NAME_PARTS.iter().any(|part| upper.contains(part)) {
+        return true;
+    }
+    !NON_SECRET_NAMES.contains(&upper.as_str())
+        && !upper.starts_with("XDG_")
+        && value.len() >= MIN_SECRET_LEN
+}
+
+/// Interpolates environment variables in `raw`.
+///
+/// Returns the interpolated string and the values substituted from the environment that
+/// should be redacted.
+pub fn interpolate_str(raw: &str) -> Result<(String, Vec<String>), InterpolationError> {
+    let mut result = String::with_capacity(raw.len());
+    let mut secrets = vec![];
+    let mut rest = raw;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start..];
+        if let Some(escaped) = after.strip_prefix("$${") {
+            result.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let expr = match after.strip_prefix("${") {
+            Some(expr) => expr,
+            None => {
+                result.push('$');
+                rest = &after[1..];
+                continue;
+            }
+        };
+        let end = expr
+            .find('}')
+            .ok_or_else(|| InterpolationError::Unterminated(raw.to_string()))?;
+        let (name, default) = match expr[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&expr[..end], None),
+        };
+        if !is_valid_name(name) {
+            return Err(InterpolationError::InvalidName(name.to_string()));
+        }
+        match (std::env::var(name).ok(), default) {
+            (Some(value), Some(default)) if value.is_empty() => result.push_str(default),
+            (Some(value), _) => {
+                result.push_str(&value);
+                if is_secret(name, &value) {
+                    secrets.push(value);
+                }
+            }
+            (None, Some(default)) => result.push_str(default),
+            (None, None) => return Err(InterpolationError::Undefined(name.to_string())),
+        }
+        rest = &expr[end + 1..];
+    }
+    result.push_str(rest);
+    Ok((result, secrets))
+}
+
+fn interpolate_value(value: &mut Value, key: &mut Vec<String>, problems: &mut Vec<Problem>) {
+    match value {
+        Value::String(raw) => match interpolate_str(raw) {
+            Ok((interpolated, secrets)) => {
+                *raw = interpolated;
+                let mut registry = SECRETS.write().unwrap();
+                registry.extend(secrets);
+            }
+            Err(err) => problems.push(Problem {
+                key: key.clone(),
+                message: err.to_string(),
+            }),
+        },
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                key.push(i.to_string());
+                interpolate_value(item, key, problems);
+                key.pop();
+            }
+        }
+        Value::Table(table) => interpolate_table(table, key, problems),
+        _ => {}
+    }
+}
+
+fn interpolate_table(table: &mut Table, key: &mut Vec<String>, problems: &mut Vec<Problem>) {
+    for (name, value) in table.iter_mut() {
+        key.push(name.clone());
+        interpolate_value(value, key, problems);
+        key.pop();
+    }
+}
+
+/// Interpolates environment variables in every string value of a config table.
+///
+/// Values that cannot be interpolated are left as they are and reported as problems.
+pub fn interpolate(table: &mut Table) -> Vec<Problem> {
+    let mut problems = vec![];
+    interpolate_table(table, &mut vec![], &mut problems);
+    problems
+}
+
+/// Replaces every secret that was substituted from the environment in `text` with [REDACTED].
+pub fn redact(text: &str) -> String {
+    redact_encoded(text, str::to_string)
+}
+
+/// Like [redact], also replacing secrets in the form `encode` gives them, for example
+/// percent-encoded in a URL.
+pub fn redact_encoded<F>(text: &str, encode: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let secrets = SECRETS.read().unwrap();
+    let mut forms: Vec<String> = secrets
+        .iter()
+        .flat_map(|secret| [encode(secret), secret.clone()])
+        .collect();
+    // Redact longer forms first, in case one contains another.
+    forms.sort_by_key(|form| std::cmp::Reverse(form.len()));
+    forms
+        .into_iter()
+        .fold(text.to_string(), |text, form| text.replace(&form, REDACTED))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_variables_and_defaults() {
+        std::env::set_var("GOKI_TEST_INTERPOLATE_SET", "value");
+        std::env::set_var("GOKI_TEST_INTERPOLATE_EMPTY", "");
+        std::env::remove_var("GOKI_TEST_INTERPOLATE_UNSET");
+        assert_eq!(
+            interpolate_str("a/${GOKI_TEST_INTERPOLATE_SET}/b")
+                .unwrap()
+                .0,
+            "a/value/b"
+        );
+        assert_eq!(
+            interpolate_str("${GOKI_TEST_INTERPOLATE_UNSET:-fallback}")
+                .unwrap()
+                .0,
+            "fallback"
+        );
+        assert_eq!(
+            interpolate_str("${GOKI_TEST_INTERPOLATE_EMPTY:-fallback}")
+                .unwrap()
+                .0,
+            "fallback"
+        );
+        assert_eq!(
+            interpolate_str("$${literal} $5").unwrap().0,
+            "${literal} $5"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_references() {
+        std::env::remove_var("GOKI_TEST_INTERPOLATE_MISSING");
+        assert_eq!(
+            interpolate_str("${GOKI_TEST_INTERPOLATE_MISSING}"),
+            Err(InterpolationError::Undefined(
+                "GOKI_TEST_INTERPOLATE_MISSING".to_string()
+            ))
+        );
+        assert_eq!(
+            interpolate_str("${1BAD}"),
+            Err(InterpolationError::InvalidName("1BAD".to_string()))
+        );
+        assert_eq!(
+            interpolate_str("a${OPEN"),
+            Err(InterpolationError::Unterminated("a${OPEN".to_string()))
+        );
+    }
+
+    #[test]
+    fn only_treats_likely_secrets_as_secrets() {
+        assert!(is_secret("RPC_API_KEY", "abc"));
+        assert!(is_secret("GITHUB_TOKEN", "ghp_0123456789"));
+        assert!(is_secret("RPC_URL", "https://rpc.example.com"));
+        assert!(!is_secret("PORT", "8899"));
+        assert!(!is_secret("HOME", "/home/someone-with-a-long-name"));
+        assert!(!is_secret("XDG_CONFIG_HOME", "/home/someone/.config"));
+        assert!(!is_secret("RPC_API_KEY", ""));
+    }
+
+    #[test]
+    fn redacts_substituted_secrets() {
+        std::env::set_var("GOKI_TEST_REDACT_TOKEN", "s3cr3t/t0k3n");
+        std::env::set_var("GOKI_TEST_REDACT_PORT", "8899");
+        let mut table: Table = toml::from_str(
+            r#"url = "http://localhost:${GOKI_TEST_REDACT_PORT}/${GOKI_TEST_REDACT_TOKEN}""#,
+        )
+        .unwrap();
+        assert!(interpolate(&mut table).is_empty());
+        let url = table["url"].as_str().unwrap();
+        assert_eq!(url, "http://localhost:8899/s3cr3t/t0k3n");
+        assert_eq!(redact(url), "http://localhost:8899/****");
+        assert_eq!(
+            redact_encoded("customUrl=s3cr3t%2Ft0k3n", |secret| secret
+                .replace('/', "%2F")),
+            "customUrl=****"
+        );
+    }
+}
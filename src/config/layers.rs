@@ -0,0 +1,494 @@
+//! Layered configuration.
+//!
+//! The configuration is merged from, in increasing order of precedence:
+//!
+//! 1. the defaults,
+//! 2. the global config at `~/.config/goki/config.toml`,
+//! 3. the nearest `Goki.toml`,
+//! 4. `GOKI_*` environment variables, where `__` separates nested keys,
+//!    for example `GOKI_GITHUB__API_URL`,
+//! 5. `--config <key>=<value>` flags.
+//!
+//! Every layer is validated against the config schema before it is merged.
+//! Environment variables whose first segment is not a config key, such as
+//! `GOKI_FOO`, are ignored.
+
+use anyhow::{format_err, Result};
+use colored::*;
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+use toml::{value::Table, Value};
+
+use super::{
+    migrate,
+    validate::{self, Diagnostic},
+    Config, RawConfig,
+};
+
+/// Name of the workspace config file.
+pub const CONFIG_FILE_NAME: &str = "Goki.toml";
+
+/// Prefix of environment variables that override config values.
+pub const ENV_PREFIX: &str = "GOKI_";
+
+/// Separator of nested keys in environment variable names.
+pub const ENV_SEPARATOR: &str = "__";
+
+/// Where a config value came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    Global(PathBuf),
+    Workspace(PathBuf),
+    Env(String),
+    Cli,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::Global(path) => write!(f, "global config {}", path.display()),
+            Origin::Workspace(path) => write!(f, "workspace config {}", path.display()),
+            Origin::Env(var) => write!(f, "environment variable {}", var),
+            Origin::Cli => write!(f, "--config flag"),
+        }
+    }
+}
+
+/// Path of the global config file, `~/.config/goki/config.toml`.
+///
+/// `$XDG_CONFIG_HOME` is used instead of `~/.config` if set.
+pub fn global_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs_next::home_dir().map(|home| home.join(".config")))?;
+    Some(config_home.join("goki").join("config.toml"))
+}
+
+/// Climbs each parent directory of `start` until we find a `Goki.toml`.
+pub fn find_workspace_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Parses a dotted key such as `http.auth."example.com".token_env`.
+pub fn parse_key(key: &str) -> Result<Vec<String>> {
+    let invalid = || format_err!("invalid config key `{}`", key);
+    let mut segments = vec![];
+    let mut rest = key;
+    loop {
+        let (segment, after) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').ok_or_else(invalid)?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => {
+                let end = rest.find('.').unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        if segment.is_empty() {
+            return Err(invalid());
+        }
+        segments.push(segment.to_string());
+        if after.is_empty() {
+            return Ok(segments);
+        }
+        rest = after.strip_prefix('.').ok_or_else(invalid)?;
+    }
+}
+
+/// Formats a key path as a dotted key, quoting segments that are not bare keys.
+pub fn format_key(path: &[String]) -> String {
+    path.iter()
+        .map(|segment| {
+            let is_bare = segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if is_bare {
+                segment.clone()
+            } else {
+                format!("\"{}\"", segment)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Parses a value given on the command line or in the environment.
+///
+/// Values that are valid TOML, such as `3`, `true` or `["a", "b"]`, are parsed as such.
+/// Anything else is a string.
+pub fn parse_value(raw: &str) -> Value {
+    match format!("value = {}", raw).parse::<Value>() {
+        Ok(Value::Table(mut table)) => table.remove("value").unwrap_or_else(|| raw.into()),
+        _ => Value::String(raw.to_string()),
+    }
+}
+
+/// Parses a `<key>=<value>` override.
+pub fn parse_override(raw: &str) -> Result<(Vec<String>, Value)> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format_err!("invalid config override `{}`: expected <key>=<value>", raw))?;
+    Ok((parse_key(key.trim())?, parse_value(value.trim())))
+}
+
+/// Reads and validates a config file.
+fn read_table(path: &Path, diagnostics: &mut Vec<Diagnostic>) -> Result<Option<Table>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format_err!("could not read {}: {}", path.display(), e))?;
+    let migrated = migrate::migrate_source(&contents)
+        .map_err(|e| format_err!("could not migrate {}: {}", path.display(), e))?;
+    if migrated.is_some() {
+        eprintln!(
+            "{} {} uses an old config format; run `goki config migrate` to update it",
+            "Warning:".yellow(),
+            path.display()
+        );
+    }
+    let (table, file_diagnostics) = validate::check_migrated_source(
+        &path.display().to_string(),
+        &contents,
+        migrated.as_deref().unwrap_or(&contents),
+    );
+    diagnostics.extend(file_diagnostics);
+    Ok(table)
+}
+
+/// Validates a value set outside of config files, such as by an environment variable.
+fn check_override(location: String, table: &Table) -> Vec<Diagnostic> {
+    validate::validate(table)
+        .into_iter()
+        .map(|problem| Diagnostic {
+            location: location.clone(),
+            message: format!("`{}`: {}", format_key(&problem.key), problem.message),
+        })
+        .collect()
+}
+
+/// Wraps `value` in tables so that it is at `path`.
+fn nest(path: &[String], value: Value) -> Table {
+    let (last, parents) = path.split_last().expect("key path must not be empty");
+    let mut table = Table::new();
+    table.insert(last.clone(), value);
+    parents.iter().rev().fold(table, |inner, segment| {
+        let mut outer = Table::new();
+        outer.insert(segment.clone(), Value::Table(inner));
+        outer
+    })
+}
+
+/// The configuration merged from all layers, remembering where each value came from.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigLayers {
+    /// The `Goki.toml` that was found, if any.
+    pub workspace_config_path: Option<PathBuf>,
+    value: Table,
+    origins: BTreeMap<Vec<String>, Origin>,
+}
+
+impl ConfigLayers {
+    /// Loads all layers, looking for `Goki.toml` in `start_dir` and its parents.
+    ///
+    /// Fails if any layer is invalid.
+    pub fn load(start_dir: &Path, overrides: &[String]) -> Result<Self> {
+        let (layers, diagnostics) = Self::load_unchecked(start_dir, overrides)?;
+        if let Some(diagnostics) = validate::format_diagnostics(&diagnostics) {
+            return Err(format_err!(
+                "invalid config:\n{}\nRun `goki config check` for details.",
+                diagnostics
+            ));
+        }
+        Ok(layers)
+    }
+
+    /// Loads all layers, returning the problems found in them instead of failing.
+    ///
+    /// Layers that could not be parsed are skipped.
+    pub fn load_unchecked(
+        start_dir: &Path,
+        overrides: &[String],
+    ) -> Result<(Self, Vec<Diagnostic>)> {
+        let mut diagnostics = vec![];
+        let mut layers = ConfigLayers::default();
+        layers.merge(
+            Value::try_from(RawConfig::from(&Config::default()))?
+                .try_into()
+                .expect("config must be a table"),
+            &Origin::Default,
+        );
+
+        if let Some(global_path) = global_config_path().filter(|path| path.is_file()) {
+            if let Some(table) = read_table(&global_path, &mut diagnostics)? {
+                layers.merge(table, &Origin::Global(global_path));
+            }
+        }
+
+        if let Some(workspace_path) = find_workspace_config(start_dir) {
+            if let Some(table) = read_table(&workspace_path, &mut diagnostics)? {
+                layers.merge(table, &Origin::Workspace(workspace_path.clone()));
+            }
+            layers.workspace_config_path = Some(workspace_path);
+        }
+
+        let mut env_vars: Vec<(String, String)> = std::env::vars()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        env_vars.sort();
+        for (name, raw_value) in env_vars {
+            let path: Vec<String> = name[ENV_PREFIX.len()..]
+                .split(ENV_SEPARATOR)
+                .map(|segment| segment.to_ascii_lowercase())
+                .collect();
+            if path.iter().any(|segment| segment.is_empty()) || !validate::is_config_key(&path[0]) {
+                continue;
+            }
+            let table = nest(&path, parse_value(&raw_value));
+            diagnostics.extend(check_override(
+                format!("environment variable {}", name),
+                &table,
+            ));
+            layers.merge(table, &Origin::Env(name));
+        }
+
+        for raw in overrides {
+            let (path, value) = parse_override(raw)?;
+            let table = nest(&path, value);
+            diagnostics.extend(check_override(format!("--config {}", raw), &table));
+            layers.merge(table, &Origin::Cli);
+        }
+        Ok((layers, diagnostics))
+    }
+
+    /// Merges `table` over the current values.
+    ///
+    /// Tables are merged key by key. Any other value, including arrays, replaces the current value.
+    pub fn merge(&mut self, table: Table, origin: &Origin) {
+        merge_table(
+            &mut self.value,
+            table,
+            &mut vec![],
+            origin,
+            &mut self.origins,
+        );
+    }
+
+    /// The merged [Config].
+    pub fn config(&self) -> Result<Config> {
+        let raw: RawConfig = Value::Table(self.value.clone())
+            .try_into()
+            .map_err(|e| format_err!("invalid config: {}", e))?;
+        Ok(raw.into())
+    }
+
+    /// The value at `path`, if set.
+    pub fn get(&self, path: &[String]) -> Option<&Value> {
+        let (first, rest) = path.split_first()?;
+        rest.iter()
+            .try_fold(self.value.get(first)?, |value, segment| value.get(segment))
+    }
+
+    /// Where the value at `path` came from.
+    ///
+    /// Tables do not have an origin, since their values may come from different layers.
+    pub fn origin(&self, path: &[String]) -> Option<&Origin> {
+        self.origins.get(path)
+    }
+
+    /// All values that are not tables, with their key paths and origins.
+    pub fn entries(&self) -> impl Iterator<Item = (&Vec<String>, &Value, &Origin)> {
+        self.origins
+            .iter()
+            .filter_map(move |(path, origin)| self.get(path).map(|value| (path, value, origin)))
+    }
+}
+
+fn merge_table(
+    target: &mut Table,
+    source: Table,
+    path: &mut Vec<String>,
+    origin: &Origin,
+    origins: &mut BTreeMap<Vec<String>, Origin>,
+) {
+    for (key, value) in source {
+        path.push(key.clone());
+        match (target.get_mut(&key), value) {
+            (Some(Value::Table(target)), Value::Table(source)) => {
+                merge_table(target, source, path, origin, origins);
+            }
+            (_, Value::Table(source)) => {
+                // Values under a table that is being replaced are no longer set.
+                origins.retain(|key, _| !key.starts_with(path));
+                let mut table = Table::new();
+                merge_table(&mut table, source, path, origin, origins);
+                target.insert(key, Value::Table(table));
+            }
+            (_, value) => {
+                origins.retain(|key, _| !key.starts_with(path));
+                origins.insert(path.clone(), origin.clone());
+                target.insert(key, value);
+            }
+        }
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(raw: &str) -> Vec<String> {
+        parse_key(raw).unwrap()
+    }
+
+    #[test]
+    fn parses_and_formats_keys() {
+        let path = key(r#"http.auth."example.com".token_env"#);
+        assert_eq!(path, vec!["http", "auth", "example.com", "token_env"]);
+        assert_eq!(format_key(&path), r#"http.auth."example.com".token_env"#);
+        for invalid in ["", "http..retries", "http.", r#"http."auth"#, r#""a"b"#] {
+            assert!(parse_key(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_values_and_overrides() {
+        assert_eq!(parse_value("3"), Value::Integer(3));
+        assert_eq!(parse_value("true"), Value::Boolean(true));
+        assert_eq!(
+            parse_value(r#"["a", "b"]"#),
+            Value::Array(vec!["a".into(), "b".into()])
+        );
+        assert_eq!(
+            parse_value("https://example.com"),
+            Value::String("https://example.com".to_string())
+        );
+        assert_eq!(
+            parse_override(" http.retries = 2 ").unwrap(),
+            (key("http.retries"), Value::Integer(2))
+        );
+        assert!(parse_override("http.retries").is_err());
+    }
+
+    #[test]
+    fn merges_layers_in_order_and_tracks_origins() {
+        let global = PathBuf::from("/global.toml");
+        let workspace = PathBuf::from("/Goki.toml");
+        let mut layers = ConfigLayers::default();
+        layers.merge(
+            toml::from_str("[http]\nretries = 1\nbackoff_ms = 10\n").unwrap(),
+            &Origin::Global(global.clone()),
+        );
+        layers.merge(
+            toml::from_str("[http]\nretries = 2\n[mirrors]\na = [\"x\"]\n").unwrap(),
+            &Origin::Workspace(workspace.clone()),
+        );
+        layers.merge(nest(&key("http.retries"), Value::Integer(3)), &Origin::Cli);
+
+        assert_eq!(layers.get(&key("http.retries")), Some(&Value::Integer(3)));
+        assert_eq!(layers.origin(&key("http.retries")), Some(&Origin::Cli));
+        assert_eq!(
+            layers.origin(&key("http.backoff_ms")),
+            Some(&Origin::Global(global))
+        );
+        assert_eq!(layers.origin(&key("http")), None);
+
+        // Arrays are replaced rather than merged.
+        layers.merge(
+            toml::from_str("[mirrors]\na = [\"y\"]\n").unwrap(),
+            &Origin::Env("GOKI_MIRRORS__A".to_string()),
+        );
+        assert_eq!(
+            layers.get(&key("mirrors.a")),
+            Some(&Value::Array(vec!["y".into()]))
+        );
+
+        // Replacing a table forgets the origins of its values.
+        layers.merge(
+            toml::from_str("mirrors = \"none\"\n").unwrap(),
+            &Origin::Workspace(workspace.clone()),
+        );
+        assert_eq!(layers.get(&key("mirrors.a")), None);
+        let entries: Vec<_> = layers
+            .entries()
+            .map(|(path, _, origin)| (format_key(path), origin.clone()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "http.backoff_ms".to_string(),
+                    Origin::Global(PathBuf::from("/global.toml"))
+                ),
+                ("http.retries".to_string(), Origin::Cli),
+                ("mirrors".to_string(), Origin::Workspace(workspace)),
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_layers_from_files_environment_and_flags() {
+        let dir = tempfile::tempdir().unwrap();
+        let global_dir = dir.path().join("config").join("goki");
+        fs::create_dir_all(&global_dir).unwrap();
+        fs::write(
+            global_dir.join("config.toml"),
+            "version = 2\n[http]\nretries = 1\nbackoff_ms = 10\nread_timeout_secs = 5\n",
+        )
+        .unwrap();
+        let workspace_dir = dir.path().join("workspace");
+        let start_dir = workspace_dir.join("programs").join("smart_wallet");
+        fs::create_dir_all(&start_dir).unwrap();
+        fs::write(
+            workspace_dir.join(CONFIG_FILE_NAME),
+            "version = 2\n[http]\nretries = 2\nbackoff_ms = 20\n",
+        )
+        .unwrap();
+        std::env::set_var("XDG_CONFIG_HOME", dir.path().join("config"));
+        std::env::set_var("GOKI_HTTP__BACKOFF_MS", "30");
+        std::env::set_var("GOKI_NOT_A_KEY", "ignored");
+        let result =
+            ConfigLayers::load_unchecked(&start_dir, &["http.read_timeout_secs=7".to_string()]);
+        let invalid_result =
+            ConfigLayers::load_unchecked(&start_dir, &["http.retries=many".to_string()]);
+        std::env::remove_var("XDG_CONFIG_HOME");
+        std::env::remove_var("GOKI_HTTP__BACKOFF_MS");
+        std::env::remove_var("GOKI_NOT_A_KEY");
+        let (layers, diagnostics) = result.unwrap();
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(
+            layers.workspace_config_path,
+            Some(workspace_dir.join(CONFIG_FILE_NAME))
+        );
+        let http = layers.config().unwrap().http;
+        assert_eq!(http.retries, 2);
+        assert_eq!(http.backoff_ms, 30);
+        assert_eq!(http.read_timeout_secs, 7);
+        assert_eq!(http.connect_timeout_secs, 10);
+        assert_eq!(
+            layers.origin(&key("http.retries")),
+            Some(&Origin::Workspace(workspace_dir.join(CONFIG_FILE_NAME)))
+        );
+        assert_eq!(
+            layers.origin(&key("http.backoff_ms")),
+            Some(&Origin::Env("GOKI_HTTP__BACKOFF_MS".to_string()))
+        );
+        assert_eq!(
+            layers.origin(&key("http.connect_timeout_secs")),
+            Some(&Origin::Default)
+        );
+
+        let (_, diagnostics) = invalid_result.unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].location, "--config http.retries=many");
+    }
+}
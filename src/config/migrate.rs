@@ -0,0 +1,226 @@
+//! Migrations of config files written in older formats.
+//!
+//! Every config file has a `version`. Files without one are version 1, the format
+//! used before the key was introduced. Migrations edit the file as a [Document], so
+//! comments and the order of keys are preserved.
+
+use anyhow::{format_err, Result};
+use toml_edit::{Document, Item, Key, Table};
+
+use super::RPC;
+
+/// Version of the config format written by this version of Goki.
+pub const CURRENT_VERSION: i64 = 2;
+
+/// A migration from one version to the next, returning a description of each change.
+type Migration = fn(&mut Document) -> Result<Vec<String>>;
+
+/// Migrations by the version they migrate from.
+const MIGRATIONS: [(i64, Migration); 1] = [(1, migrate_v1)];
+
+/// The version of a config file.
+pub fn version(doc: &Document) -> Result<i64> {
+    match doc.get("version") {
+        None => Ok(1),
+        Some(item) => item
+            .as_integer()
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| format_err!("`version` must be a positive integer")),
+    }
+}
+
+/// Migrates a config file to the current version, returning a description of each change.
+pub fn migrate(doc: &mut Document) -> Result<Vec<String>> {
+    let mut from = version(doc)?;
+    if from > CURRENT_VERSION {
+        return Err(format_err!(
+            "config version {} is newer than the latest version supported by this version of goki, {}; please upgrade goki",
+            from,
+            CURRENT_VERSION
+        ));
+    }
+    let mut changes = vec![];
+    while from < CURRENT_VERSION {
+        let (_, migration) = MIGRATIONS
+            .iter()
+            .find(|(version, _)| *version == from)
+            .expect("migrations must cover every version");
+        changes.extend(migration(doc)?);
+        from += 1;
+        set_version(doc, from);
+    }
+    Ok(changes)
+}
+
+/// Migrates the contents of a config file, returning the migrated contents if they changed.
+///
+/// Contents that cannot be parsed are returned unchanged, so that parse errors are reported
+/// against the original file.
+pub fn migrate_source(source: &str) -> Result<Option<String>> {
+    let mut doc = match source.parse::<Document>() {
+        Ok(doc) => doc,
+        Err(_) => return Ok(None),
+    };
+    if version(&doc)? == CURRENT_VERSION {
+        return Ok(None);
+    }
+    migrate(&mut doc)?;
+    Ok(Some(doc.to_string()))
+}
+
+/// Sets `version`, placing it before any other value.
+fn set_version(doc: &mut Document, version: i64) {
+    let root = doc.as_table_mut();
+    if root.contains_key("version") {
+        root.insert("version", toml_edit::value(version));
+        return;
+    }
+    let keys: Vec<String> = root
+        .iter()
+        .filter(|(_, item)| item.is_value())
+        .map(|(key, _)| key.to_string())
+        .collect();
+    let mut values: Vec<(Key, Item)> = keys
+        .iter()
+        .filter_map(|key| root.remove_entry(key))
+        .collect();
+    // Comments at the top of the file stay above `version`.
+    let mut version_key = Key::new("version");
+    if let Some((first, _)) = values.first_mut() {
+        if let Some(prefix) = first.decor().prefix().map(str::to_string) {
+            version_key.decor_mut().set_prefix(prefix);
+            first.decor_mut().set_prefix("");
+        }
+    }
+    root.insert_formatted(&version_key, toml_edit::value(version));
+    for (key, item) in values {
+        root.insert_formatted(&key, item);
+    }
+}
+
+/// Version 2 moves the URLs in `[rpc_endpoints]` to `url` in `[clusters.<name>]`.
+///
+/// URLs that are the default of their cluster are removed.
+fn migrate_v1(doc: &mut Document) -> Result<Vec<String>> {
+    let rpc_endpoints = match doc.as_table_mut().remove("rpc_endpoints") {
+        Some(Item::Table(table)) => table,
+        Some(_) => return Err(format_err!("`rpc_endpoints` must be a table")),
+        None => return Ok(vec![]),
+    };
+    let defaults = RPC::default();
+    let mut changes = vec![];
+    for (name, item) in rpc_endpoints.iter() {
+        let url = item
+            .as_str()
+            .ok_or_else(|| format_err!("`rpc_endpoints.{}` must be a string", name))?;
+        let default = match name {
+            "mainnet" => Some(&defaults.mainnet),
+            "devnet" => Some(&defaults.devnet),
+            "testnet" => Some(&defaults.testnet),
+            "localnet" => Some(&defaults.localnet),
+            "debug" => Some(&defaults.debug),
+            _ => None,
+        };
+        if default.map(String::as_str) == Some(url) {
+            changes.push(format!(
+                "removed rpc_endpoints.{}, which is the default URL",
+                name
+            ));
+            continue;
+        }
+
+        let clusters = doc
+            .as_table_mut()
+            .entry("clusters")
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_mut()
+            .ok_or_else(|| format_err!("`clusters` must be a table"))?;
+        let cluster = clusters
+            .entry(name)
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                if let Some(position) = rpc_endpoints.position() {
+                    table.set_position(position);
+                }
+                Item::Table(table)
+            })
+            .as_table_mut()
+            .ok_or_else(|| format_err!("`clusters.{}` must be a table", name))?;
+        if cluster.contains_key("url") {
+            changes.push(format!(
+                "removed rpc_endpoints.{}, which is overridden by clusters.{}.url",
+                name, name
+            ));
+        } else {
+            cluster.insert("url", toml_edit::value(url));
+            changes.push(format!(
+                "moved rpc_endpoints.{} to clusters.{}.url",
+                name, name
+            ));
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_rpc_endpoints_to_clusters() {
+        let source = r#"# Goki config
+upgrade_authority_keypair = "keys/upgrader.json"
+
+[rpc_endpoints]
+mainnet = "https://rpc.example.com"
+devnet = "https://api.devnet.solana.com"
+testnet = "https://testnet.example.com"
+
+[clusters.testnet]
+url = "https://override.example.com"
+"#;
+        let mut doc = source.parse::<Document>().unwrap();
+        let changes = migrate(&mut doc).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                "moved rpc_endpoints.mainnet to clusters.mainnet.url",
+                "removed rpc_endpoints.devnet, which is the default URL",
+                "removed rpc_endpoints.testnet, which is overridden by clusters.testnet.url",
+            ]
+        );
+        assert_eq!(version(&doc).unwrap(), CURRENT_VERSION);
+        assert!(doc.get("rpc_endpoints").is_none());
+        assert_eq!(
+            doc["clusters"]["mainnet"]["url"].as_str(),
+            Some("https://rpc.example.com")
+        );
+        assert_eq!(
+            doc["clusters"]["testnet"]["url"].as_str(),
+            Some("https://override.example.com")
+        );
+        assert!(doc.to_string().starts_with("# Goki config\nversion = 2\n"));
+    }
+
+    #[test]
+    fn leaves_current_configs_unchanged() {
+        assert_eq!(migrate_source("version = 2\n").unwrap(), None);
+        assert_eq!(migrate_source("not toml = [").unwrap(), None);
+        assert_eq!(
+            migrate_source("").unwrap(),
+            Some("version = 2\n".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_newer_and_invalid_versions() {
+        let mut doc = "version = 3".parse::<Document>().unwrap();
+        assert!(migrate(&mut doc).is_err());
+        let mut doc = "version = 0".parse::<Document>().unwrap();
+        assert!(migrate(&mut doc).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use solana_sdk::commitment_config::CommitmentLevel;
+
+use crate::location::registry::DEFAULT_INDEX_URL;
+
+pub mod edit;
+pub mod interpolate;
+mod layers;
+pub mod migrate;
+pub mod validate;
+
+pub use self::layers::{
+    find_workspace_config, format_key, global_config_path, parse_key, parse_value, ConfigLayers,
+    Origin, CONFIG_FILE_NAME,
+};
+
+pub struct WithPath<T> {
+    inner: T,
+    path: PathBuf,
+}
+
+impl<T> WithPath<T> {
+    pub fn new(inner: T, path: PathBuf) -> Self {
+        Self { inner, path }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> std::convert::AsRef<T> for WithPath<T> {
+    fn as_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> std::ops::Deref for WithPath<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<T> std::ops::DerefMut for WithPath<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// URLs of the well-known clusters. These may be overridden in `[clusters.<name>]`.
+    pub rpc_endpoints: RPC,
+    pub upgrade_authority_keypair: Option<String>,
+    /// Clusters that may be passed to `--cluster`, by name.
+    pub clusters: BTreeMap<String, ClusterConfig>,
+    pub github: GitHub,
+    pub registry: Registry,
+    pub gateways: Gateways,
+    pub http: Http,
+    /// Mirror URLs of artifacts, keyed by location, tried in order if the location cannot be fetched.
+    pub mirrors: BTreeMap<String, Vec<String>>,
+    /// Programs managed by the workspace, by name.
+    pub programs: BTreeMap<String, ProgramConfig>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RPC {
+    pub mainnet: String,
+    pub devnet: String,
+    pub testnet: String,
+    pub localnet: String,
+    pub debug: String,
+}
+
+impl Default for RPC {
+    fn default() -> Self {
+        Self {
+            mainnet: "https://api.mainnet-beta.solana.com".to_string(),
+            devnet: "https://api.devnet.solana.com".to_string(),
+            testnet: "https://api.testnet.solana.com".to_string(),
+            localnet: "http://127.0.0.1:8899".to_string(),
+            debug: "http://34.90.18.145:8899".to_string(),
+        }
+    }
+}
+
+/// A cluster defined in `[clusters.<name>]`, and the policies that apply to it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClusterConfig {
+    /// JSON RPC URL. Required unless this is a well-known cluster.
+    pub url: Option<String>,
+    /// Websocket URL. Defaults to one derived from the RPC URL.
+    pub ws_url: Option<String>,
+    /// Commitment level used for requests.
+    pub commitment: Option<CommitmentLevel>,
+    /// Upgrade authority on this cluster: a path to a keypair, or a public key if
+    /// upgrades are signed elsewhere, e.g. by a Smart Wallet.
+    ///
+    /// Takes precedence over `upgrade_authority_keypair`.
+    pub upgrade_authority: Option<String>,
+    /// Whether the upgrade authority may sign transactions locally.
+    pub allow_local_signing: bool,
+    /// Whether to ask for confirmation before sending transactions.
+    pub require_confirmation: bool,
+    /// Minimum balance of the deployer, in SOL, required to send transactions.
+    pub min_deployer_balance: Option<f64>,
+    /// Headers sent with RPC requests made by Goki, for example for authentication.
+    pub headers: BTreeMap<String, String>,
+    /// Block explorer used for links: `solana-explorer`, `solscan`, `solanafm` or a URL
+    /// template. Defaults to `solana-explorer`.
+    pub explorer: Option<String>,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            ws_url: None,
+            commitment: None,
+            upgrade_authority: None,
+            allow_local_signing: true,
+            require_confirmation: false,
+            min_deployer_balance: None,
+            headers: BTreeMap::new(),
+            explorer: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitHub {
+    /// Base URL of the GitHub REST API.
+    pub api_url: String,
+}
+
+impl Default for GitHub {
+    fn default() -> Self {
+        Self {
+            api_url: "https://api.github.com".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Registry {
+    /// Base URL of the Solana Program Registry index.
+    ///
+    /// This may also be a `file://` URL or a path to a local copy of the index.
+    pub index_url: String,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            index_url: DEFAULT_INDEX_URL.to_string(),
+        }
+    }
+}
+
+/// Gateways used to fetch content-addressed programs, tried in order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Gateways {
+    /// IPFS gateways. The CID is appended to each URL.
+    pub ipfs: Vec<String>,
+    /// Arweave gateways. The transaction ID is appended to each URL.
+    pub arweave: Vec<String>,
+}
+
+impl Default for Gateways {
+    fn default() -> Self {
+        Self {
+            ipfs: vec![
+                "https://ipfs.io/ipfs/".to_string(),
+                "https://cloudflare-ipfs.com/ipfs/".to_string(),
+                "https://dweb.link/ipfs/".to_string(),
+            ],
+            arweave: vec!["https://arweave.net/".to_string()],
+        }
+    }
+}
+
+/// Settings of the HTTP client used to download programs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Http {
+    /// Seconds to wait for a connection to be established.
+    pub connect_timeout_secs: u64,
+    /// Seconds to wait for a response or for the next chunk of a response body.
+    pub read_timeout_secs: u64,
+    /// Number of times a failed download is retried.
+    pub retries: u32,
+    /// Milliseconds to wait before the first retry. This doubles after each retry.
+    pub backoff_ms: u64,
+    /// Proxy that all requests are sent through, e.g. `http://proxy.example.com:8080`.
+    pub proxy: Option<String>,
+    /// Authentication for requests to each host.
+    pub auth: BTreeMap<String, HostAuth>,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            retries: 4,
+            backoff_ms: 500,
+            proxy: None,
+            auth: BTreeMap::new(),
+        }
+    }
+}
+
+/// Authentication for requests to a host.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HostAuth {
+    /// Environment variable containing the token.
+    pub token_env: String,
+    /// Header the token is sent in. Defaults to `Authorization: Bearer <token>`.
+    pub header: Option<String>,
+}
+
+/// A program declared in `[programs.<name>]`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProgramConfig {
+    /// Program ID on every cluster, unless overridden in `clusters`.
+    pub program_id: Option<String>,
+    /// Default location of the program binary, for example
+    /// `gh:smart_wallet:GokiProtocol/goki@{version}`. `{version}` is replaced with `--version`.
+    pub location: Option<String>,
+    /// Public key of the expected upgrade authority on every cluster, unless overridden in `clusters`.
+    pub upgrade_authority: Option<String>,
+    /// Settings that differ between clusters, by cluster name.
+    pub clusters: BTreeMap<String, ProgramClusterConfig>,
+}
+
+/// Settings of a program on a single cluster, in `[programs.<name>.clusters.<cluster>]`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProgramClusterConfig {
+    pub program_id: Option<String>,
+    pub upgrade_authority: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RawConfig {
+    version: Option<i64>,
+    upgrade_authority_keypair: Option<String>,
+    clusters: Option<BTreeMap<String, ClusterConfig>>,
+    github: Option<GitHub>,
+    registry: Option<Registry>,
+    gateways: Option<Gateways>,
+    http: Option<Http>,
+    mirrors: Option<BTreeMap<String, Vec<String>>>,
+    programs: Option<BTreeMap<String, ProgramConfig>>,
+}
+
+impl From<&Config> for RawConfig {
+    fn from(cfg: &Config) -> Self {
+        RawConfig {
+            version: Some(migrate::CURRENT_VERSION),
+            upgrade_authority_keypair: cfg.upgrade_authority_keypair.clone(),
+            clusters: Some(cfg.clusters.clone()),
+            github: Some(cfg.github.clone()),
+            registry: Some(cfg.registry.clone()),
+            gateways: Some(cfg.gateways.clone()),
+            http: Some(cfg.http.clone()),
+            mirrors: Some(cfg.mirrors.clone()),
+            programs: Some(cfg.programs.clone()),
+        }
+    }
+}
+
+impl From<RawConfig> for Config {
+    fn from(cfg: RawConfig) -> Self {
+        Config {
+            rpc_endpoints: RPC::default(),
+            upgrade_authority_keypair: cfg.upgrade_authority_keypair,
+            clusters: cfg.clusters.unwrap_or_default(),
+            github: cfg.github.unwrap_or_default(),
+            registry: cfg.registry.unwrap_or_default(),
+            gateways: cfg.gateways.unwrap_or_default(),
+            http: cfg.http.unwrap_or_default(),
+            mirrors: cfg.mirrors.unwrap_or_default(),
+            programs: cfg.programs.unwrap_or_default(),
+        }
+    }
+}
+
+impl Config {
+    /// Contents of a new `Goki.toml`.
+    ///
+    /// Only the version is set, so that later changes to the defaults apply to the workspace.
+    /// The clusters are listed in comments as a starting point.
+    pub fn template() -> String {
+        let rpc = RPC::default();
+        let mut template = format!(
+            "version = {}\n\
+             \n\
+             # The well-known clusters use these RPC URLs unless `url` is set. Clusters with other\n\
+             # names may be added and passed to `--cluster`.\n\
+             #\n\
+             # [clusters.mainnet]\n\
+             # url = \"{}\"\n\
+             # upgrade_authority = \"<keypair path or public key>\"\n\
+             # require_confirmation = true\n",
+            migrate::CURRENT_VERSION,
+            rpc.mainnet
+        );
+        for (name, url) in [
+            ("devnet", &rpc.devnet),
+            ("testnet", &rpc.testnet),
+            ("localnet", &rpc.localnet),
+        ] {
+            template.push_str(&format!("#\n# [clusters.{}]\n# url = \"{}\"\n", name, url));
+        }
+        template
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cfg = toml::to_string(&RawConfig::from(self)).expect("Must be well formed");
+        write!(f, "{}", cfg)
+    }
+}
+
+impl FromStr for Config {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let migrated = migrate::migrate_source(s)?;
+        let (table, diagnostics) =
+            validate::check_source("config", migrated.as_deref().unwrap_or(s));
+        if let Some(diagnostics) = validate::format_diagnostics(&diagnostics) {
+            return Err(anyhow::format_err!(
+                "Unable to deserialize config:\n{}",
+                diagnostics
+            ));
+        }
+        let cfg: RawConfig = toml::Value::Table(table.unwrap_or_default())
+            .try_into()
+            .map_err(|e| anyhow::format_err!("Unable to deserialize config: {}", e.to_string()))?;
+        Ok(cfg.into())
+    }
+}
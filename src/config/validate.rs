@@ -0,0 +1,580 @@
+//! Validates configuration files against the schema of [super::Config].
+//!
+//! Unlike deserialization, which stops at the first error, validation reports every
+//! problem it finds, with the line and column of the offending key where possible.
+
+use reqwest::Url;
+use solana_sdk::{pubkey::Pubkey, signature::read_keypair_file};
+use std::{fmt, path::Path, str::FromStr};
+use toml::{value::Table, Value};
+
+use super::{format_key, interpolate::interpolate, parse_key, Config};
+use crate::{cluster::ClusterRef, explorer::Explorer, policy::is_signer_uri};
+
+/// Expected shape of a config value.
+enum Schema {
+    String,
+    /// A URL with one of the given schemes.
+    Url(&'static [&'static str]),
+    Bool,
+    /// A non-negative integer.
+    Integer,
+    /// A number, which may be written as an integer.
+    Float,
+    Pubkey,
+    Commitment,
+    /// A block explorer preset or URL template.
+    Explorer,
+    Array(&'static Schema),
+    /// A table with a fixed set of keys: `(name, schema, required)`.
+    Table(&'static [(&'static str, Schema, bool)]),
+    /// A table with arbitrary keys whose values all have the same schema.
+    Map(&'static Schema),
+}
+
+const HTTP_URL: Schema = Schema::Url(&["http", "https"]);
+const WS_URL: Schema = Schema::Url(&["ws", "wss"]);
+
+const CLUSTER: Schema = Schema::Table(&[
+    ("url", HTTP_URL, false),
+    ("ws_url", WS_URL, false),
+    ("commitment", Schema::Commitment, false),
+    ("upgrade_authority", Schema::String, false),
+    ("allow_local_signing", Schema::Bool, false),
+    ("require_confirmation", Schema::Bool, false),
+    ("min_deployer_balance", Schema::Float, false),
+    ("headers", Schema::Map(&Schema::String), false),
+    ("explorer", Schema::Explorer, false),
+]);
+
+const PROGRAM: Schema = Schema::Table(&[
+    ("program_id", Schema::Pubkey, false),
+    ("location", Schema::String, false),
+    ("upgrade_authority", Schema::Pubkey, false),
+    (
+        "clusters",
+        Schema::Map(&Schema::Table(&[
+            ("program_id", Schema::Pubkey, false),
+            ("upgrade_authority", Schema::Pubkey, false),
+        ])),
+        false,
+    ),
+]);
+
+const CONFIG: Schema = Schema::Table(&[
+    ("version", Schema::Integer, false),
+    ("upgrade_authority_keypair", Schema::String, false),
+    ("clusters", Schema::Map(&CLUSTER), false),
+    (
+        "github",
+        Schema::Table(&[("api_url", HTTP_URL, false)]),
+        false,
+    ),
+    (
+        "registry",
+        Schema::Table(&[("index_url", Schema::String, false)]),
+        false,
+    ),
+    (
+        "gateways",
+        Schema::Table(&[
+            ("ipfs", Schema::Array(&HTTP_URL), false),
+            ("arweave", Schema::Array(&HTTP_URL), false),
+        ]),
+        false,
+    ),
+    (
+        "http",
+        Schema::Table(&[
+            ("connect_timeout_secs", Schema::Integer, false),
+            ("read_timeout_secs", Schema::Integer, false),
+            ("retries", Schema::Integer, false),
+            ("backoff_ms", Schema::Integer, false),
+            ("proxy", Schema::Url(&["http", "https", "socks5"]), false),
+            (
+                "auth",
+                Schema::Map(&Schema::Table(&[
+                    ("token_env", Schema::String, true),
+                    ("header", Schema::String, false),
+                ])),
+                false,
+            ),
+        ]),
+        false,
+    ),
+    ("mirrors", Schema::Map(&Schema::Array(&HTTP_URL)), false),
+    ("programs", Schema::Map(&PROGRAM), false),
+]);
+
+const COMMITMENT_LEVELS: [&str; 3] = ["processed", "confirmed", "finalized"];
+
+/// A problem with the value at a key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub key: Vec<String>,
+    pub message: String,
+}
+
+/// A [Problem], located in the source it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Where the problem is, e.g. `Goki.toml:3:1` or `environment variable GOKI_HTTP__RETRIES`.
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "a string",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Boolean(_) => "a boolean",
+        Value::Datetime(_) => "a datetime",
+        Value::Array(_) => "an array",
+        Value::Table(_) => "a table",
+    }
+}
+
+/// Number of single-character edits needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + if ca == *cb { 0 } else { 1 };
+            cur.push(substitution.min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+fn unknown_key_message(key: &str, fields: &[(&'static str, Schema, bool)]) -> String {
+    let suggestion = fields
+        .iter()
+        .map(|(name, _, _)| (edit_distance(key, name), name))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance);
+    match suggestion {
+        Some((_, name)) => format!("unknown key; did you mean `{}`?", name),
+        None => format!(
+            "unknown key; expected one of {}",
+            fields
+                .iter()
+                .map(|(name, _, _)| format!("`{}`", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn check(value: &Value, schema: &Schema, key: &mut Vec<String>, problems: &mut Vec<Problem>) {
+    let mut problem = |message: String| {
+        problems.push(Problem {
+            key: key.clone(),
+            message,
+        })
+    };
+    match (schema, value) {
+        (Schema::String, Value::String(_))
+        | (Schema::Bool, Value::Boolean(_))
+        | (Schema::Float, Value::Float(_) | Value::Integer(_)) => {}
+        (Schema::Integer, Value::Integer(n)) => {
+            if *n < 0 {
+                problem("must not be negative".to_string());
+            }
+        }
+        (Schema::Url(schemes), Value::String(url)) => match Url::parse(url) {
+            Ok(url) if schemes.contains(&url.scheme()) => {}
+            Ok(url) => problem(format!(
+                "URL scheme `{}` is not supported; expected {}",
+                url.scheme(),
+                schemes.join(" or ")
+            )),
+            Err(err) => problem(format!("invalid URL `{}`: {}", url, err)),
+        },
+        (Schema::Pubkey, Value::String(pubkey)) => {
+            if Pubkey::from_str(pubkey).is_err() {
+                problem(format!("invalid public key `{}`", pubkey));
+            }
+        }
+        (Schema::Commitment, Value::String(level)) => {
+            if !COMMITMENT_LEVELS.contains(&level.as_str()) {
+                problem(format!(
+                    "invalid commitment `{}`; expected one of {}",
+                    level,
+                    COMMITMENT_LEVELS.join(", ")
+                ));
+            }
+        }
+        (Schema::Explorer, Value::String(explorer)) => {
+            if let Err(err) = Explorer::from_str(explorer) {
+                problem(err.to_string());
+            }
+        }
+        (Schema::Array(item_schema), Value::Array(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                key.push(i.to_string());
+                check(item, item_schema, key, problems);
+                key.pop();
+            }
+        }
+        (Schema::Table(fields), Value::Table(table)) => {
+            for (name, value) in table.iter() {
+                key.push(name.clone());
+                match fields.iter().find(|(field, _, _)| field == name) {
+                    Some((_, field_schema, _)) => check(value, field_schema, key, problems),
+                    None => problems.push(Problem {
+                        key: key.clone(),
+                        message: unknown_key_message(name, fields),
+                    }),
+                }
+                key.pop();
+            }
+            for (name, _, required) in fields.iter() {
+                if *required && !table.contains_key(*name) {
+                    problems.push(Problem {
+                        key: key.clone(),
+                        message: format!("missing required key `{}`", name),
+                    });
+                }
+            }
+        }
+        (Schema::Map(value_schema), Value::Table(table)) => {
+            for (name, value) in table.iter() {
+                key.push(name.clone());
+                check(value, value_schema, key, problems);
+                key.pop();
+            }
+        }
+        (schema, value) => {
+            let expected = match schema {
+                Schema::String
+                | Schema::Url(_)
+                | Schema::Pubkey
+                | Schema::Commitment
+                | Schema::Explorer => "a string",
+                Schema::Bool => "a boolean",
+                Schema::Integer => "an integer",
+                Schema::Float => "a number",
+                Schema::Array(_) => "an array",
+                Schema::Table(_) | Schema::Map(_) => "a table",
+            };
+            problem(format!("expected {}, found {}", expected, type_name(value)));
+        }
+    }
+}
+
+/// Whether `name` is a top-level config key.
+pub fn is_config_key(name: &str) -> bool {
+    match CONFIG {
+        Schema::Table(fields) => fields.iter().any(|(field, _, _)| *field == name),
+        _ => unreachable!(),
+    }
+}
+
+/// Formats diagnostics one per line, or returns `None` if there are none.
+pub fn format_diagnostics(diagnostics: &[Diagnostic]) -> Option<String> {
+    if diagnostics.is_empty() {
+        return None;
+    }
+    Some(
+        diagnostics
+            .iter()
+            .map(|diagnostic| format!("  {}", diagnostic))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+/// Checks the structure of a config table: unknown keys, types and URLs.
+pub fn validate(table: &Table) -> Vec<Problem> {
+    let mut problems = vec![];
+    check(
+        &Value::Table(table.clone()),
+        &CONFIG,
+        &mut vec![],
+        &mut problems,
+    );
+    check_cluster_names(table, &mut vec!["clusters".to_string()], &mut problems);
+    if let Some(Value::Table(programs)) = table.get("programs") {
+        for (name, program) in programs.iter() {
+            if let Value::Table(program) = program {
+                let mut key = vec!["programs".to_string(), name.clone(), "clusters".to_string()];
+                check_cluster_names(program, &mut key, &mut problems);
+            }
+        }
+    }
+    problems
+}
+
+/// Checks that the keys of the table at the last segment of `key` are cluster names.
+fn check_cluster_names(parent: &Table, key: &mut Vec<String>, problems: &mut Vec<Problem>) {
+    let clusters = match key.last().and_then(|name| parent.get(name)) {
+        Some(Value::Table(clusters)) => clusters,
+        _ => return,
+    };
+    for name in clusters.keys() {
+        if let Err(err) = ClusterRef::from_str(name) {
+            key.push(name.clone());
+            problems.push(Problem {
+                key: key.clone(),
+                message: err.to_string(),
+            });
+            key.pop();
+        }
+    }
+}
+
+/// Finds the 1-based line and column where `key` is defined in `source`.
+///
+/// Keys inside inline tables and arrays are located at their enclosing key.
+pub fn locate(source: &str, key: &[String]) -> Option<(usize, usize)> {
+    let mut table: Vec<String> = vec![];
+    let mut best: Option<(usize, (usize, usize))> = None;
+    for (i, line) in source.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let column = line.len() - trimmed.len() + 1;
+        let defined = if let Some(header) = trimmed.strip_prefix('[') {
+            let header = header.trim_start_matches('[');
+            let header = match header.find(']') {
+                Some(end) => &header[..end],
+                None => continue,
+            };
+            match parse_key(header.trim()) {
+                Ok(path) => {
+                    table = path.clone();
+                    path
+                }
+                Err(_) => continue,
+            }
+        } else {
+            let name = match trimmed.split_once('=') {
+                Some((name, _)) if !trimmed.starts_with('#') => name,
+                _ => continue,
+            };
+            match parse_key(name.trim()) {
+                Ok(path) => table.iter().cloned().chain(path).collect(),
+                Err(_) => continue,
+            }
+        };
+        if defined.as_slice() == key {
+            return Some((i + 1, column));
+        }
+        let is_better = matches!(best, Some((len, _)) if defined.len() <= len);
+        if key.starts_with(&defined) && !is_better {
+            best = Some((defined.len(), (i + 1, column)));
+        }
+    }
+    best.map(|(_, location)| location)
+}
+
+/// Parses, interpolates and validates the contents of a config file, returning the
+/// interpolated table if it could be parsed.
+pub fn check_source(name: &str, source: &str) -> (Option<Table>, Vec<Diagnostic>) {
+    check_migrated_source(name, source, source)
+}
+
+/// Like [check_source], for contents that were migrated from `original`.
+///
+/// Problems are located in the original contents, since that is what the file contains.
+pub fn check_migrated_source(
+    name: &str,
+    original: &str,
+    source: &str,
+) -> (Option<Table>, Vec<Diagnostic>) {
+    let mut table = match source.parse::<Value>() {
+        Ok(Value::Table(table)) => table,
+        Ok(_) => unreachable!("TOML documents are tables"),
+        Err(err) => {
+            let message = err.to_string();
+            let message = match message.rfind(" at line ") {
+                Some(end) => message[..end].to_string(),
+                None => message,
+            };
+            let location = match err.line_col() {
+                Some((line, col)) => format!("{}:{}:{}", name, line + 1, col + 1),
+                None => name.to_string(),
+            };
+            return (None, vec![Diagnostic { location, message }]);
+        }
+    };
+    let mut problems = interpolate(&mut table);
+    // Values that could not be interpolated are not validated.
+    let uninterpolated: Vec<Vec<String>> = problems.iter().map(|p| p.key.clone()).collect();
+    problems.extend(
+        validate(&table)
+            .into_iter()
+            .filter(|problem| !uninterpolated.contains(&problem.key)),
+    );
+    let mut located: Vec<(Option<(usize, usize)>, Problem)> = problems
+        .into_iter()
+        .map(|problem| (locate(original, &problem.key), problem))
+        .collect();
+    located.sort_by_key(|(line_col, _)| *line_col);
+    let diagnostics = located
+        .into_iter()
+        .map(|(line_col, problem)| Diagnostic {
+            location: match line_col {
+                Some((line, col)) => format!("{}:{}:{}", name, line, col),
+                None => name.to_string(),
+            },
+            message: format!("`{}`: {}", format_key(&problem.key), problem.message),
+        })
+        .collect();
+    (Some(table), diagnostics)
+}
+
+/// Checks that a keypair file exists and can be read. Signer URIs such as `usb://ledger` are
+/// not checked.
+fn check_keypair_file(path: &str) -> Option<String> {
+    if is_signer_uri(path) {
+        return None;
+    }
+    if !Path::new(path).exists() {
+        return Some(format!("keypair file {} does not exist", path));
+    }
+    read_keypair_file(path)
+        .err()
+        .map(|err| format!("could not read keypair {}: {}", path, err))
+}
+
+/// Checks the merged config: referenced keypairs must exist and parse, and named
+/// clusters must have a URL.
+pub fn check_config(cfg: &Config) -> Vec<Problem> {
+    let mut problems = vec![];
+    if let Some(path) = &cfg.upgrade_authority_keypair {
+        if Pubkey::from_str(path).is_err() {
+            if let Some(message) = check_keypair_file(path) {
+                problems.push(Problem {
+                    key: vec!["upgrade_authority_keypair".to_string()],
+                    message,
+                });
+            }
+        }
+    }
+    for (name, cluster) in cfg.clusters.iter() {
+        let key = |field: &str| vec!["clusters".to_string(), name.clone(), field.to_string()];
+        if cluster.url.is_none() && matches!(ClusterRef::from_str(name), Ok(ClusterRef::Named(_))) {
+            problems.push(Problem {
+                key: key("url"),
+                message: format!("cluster {} must have a URL", name),
+            });
+        }
+        if let Some(authority) = &cluster.upgrade_authority {
+            if Pubkey::from_str(authority).is_err() {
+                if let Some(message) = check_keypair_file(authority) {
+                    problems.push(Problem {
+                        key: key("upgrade_authority"),
+                        message,
+                    });
+                }
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(raw: &str) -> Vec<String> {
+        parse_key(raw).unwrap()
+    }
+
+    const SOURCE: &str = r#"version = 1
+gateways = { ipfs = ["https://ipfs.io"] }
+# url = "https://commented.out"
+
+[clusters.private]
+url = "https://rpc.example.com"
+  ws_url = "wss://rpc.example.com"
+
+[http]
+auth."example.com".token_env = "TOKEN"
+"#;
+
+    #[test]
+    fn locates_keys() {
+        assert_eq!(locate(SOURCE, &key("version")), Some((1, 1)));
+        assert_eq!(locate(SOURCE, &key("clusters.private")), Some((5, 1)));
+        assert_eq!(locate(SOURCE, &key("clusters.private.url")), Some((6, 1)));
+        assert_eq!(
+            locate(SOURCE, &key("clusters.private.ws_url")),
+            Some((7, 3))
+        );
+        assert_eq!(
+            locate(SOURCE, &key(r#"http.auth."example.com".token_env"#)),
+            Some((10, 1))
+        );
+    }
+
+    #[test]
+    fn locates_undefined_keys_at_their_closest_parent() {
+        assert_eq!(locate(SOURCE, &key("gateways.ipfs.0")), Some((2, 1)));
+        assert_eq!(
+            locate(SOURCE, &key(r#"http.auth."example.com".header"#)),
+            Some((9, 1))
+        );
+        assert_eq!(locate(SOURCE, &key("mirrors")), None);
+    }
+
+    #[test]
+    fn reports_every_problem_with_its_location() {
+        let source = r#"[http]
+retrys = 3
+read_timeout_secs = -1
+
+[clusters.private]
+url = "ftp://rpc.example.com"
+"#;
+        let (table, diagnostics) = check_source("Goki.toml", source);
+        assert!(table.is_some());
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    location: "Goki.toml:2:1".to_string(),
+                    message: "`http.retrys`: unknown key; did you mean `retries`?".to_string(),
+                },
+                Diagnostic {
+                    location: "Goki.toml:3:1".to_string(),
+                    message: "`http.read_timeout_secs`: must not be negative".to_string(),
+                },
+                Diagnostic {
+                    location: "Goki.toml:6:1".to_string(),
+                    message: "`clusters.private.url`: URL scheme `ftp` is not supported; \
+                              expected http or https"
+                        .to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_their_location() {
+        let (table, diagnostics) = check_source("Goki.toml", "version = 1\nversion = \n");
+        assert!(table.is_none());
+        assert_eq!(diagnostics.len(), 1);
+        assert!(
+            diagnostics[0].location.starts_with("Goki.toml:2:"),
+            "{}",
+            diagnostics[0]
+        );
+    }
+
+    #[test]
+    fn accepts_valid_sources() {
+        let (table, diagnostics) = check_source("Goki.toml", SOURCE);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(table.unwrap()["version"], Value::Integer(1));
+    }
+}
@@ -0,0 +1,294 @@
+//! Links to block explorers, configured per cluster with `explorer` in `[clusters.<name>]`.
+//!
+//! The explorer is one of the [PRESETS], or a URL template in which `{kind}` is replaced
+//! with `address` or `tx`, `{id}` with the address or signature, `{cluster}` with the
+//! name of the cluster and `{url}` with its URL-encoded RPC URL.
+
+use anchor_client::Cluster;
+use anyhow::{format_err, Result};
+use colored::*;
+use reqwest::Url;
+use std::{process::Output, str::FromStr};
+
+use crate::{cluster::ClusterRef, config::interpolate::redact_encoded, workspace::Workspace};
+
+/// Names of the built-in explorers.
+pub const PRESETS: [&str; 3] = ["solana-explorer", "solscan", "solanafm"];
+
+/// What a link points to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkKind {
+    /// An account, such as a program or buffer.
+    Address,
+    Transaction,
+}
+
+impl LinkKind {
+    fn path(self) -> &'static str {
+        match self {
+            LinkKind::Address => "address",
+            LinkKind::Transaction => "tx",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Explorer {
+    SolanaExplorer,
+    Solscan,
+    SolanaFm,
+    /// A URL template.
+    Custom(String),
+}
+
+impl FromStr for Explorer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "solana-explorer" => return Ok(Explorer::SolanaExplorer),
+            "solscan" => return Ok(Explorer::Solscan),
+            "solanafm" => return Ok(Explorer::SolanaFm),
+            _ => {}
+        }
+        if !s.contains("{id}") {
+            return Err(format_err!(
+                "unknown explorer `{}`; expected one of {} or a URL template containing `{{id}}`",
+                s,
+                PRESETS.join(", ")
+            ));
+        }
+        let example = s
+            .replace("{kind}", "address")
+            .replace("{id}", "id")
+            .replace("{cluster}", "cluster")
+            .replace("{url}", "url");
+        match Url::parse(&example) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+                Ok(Explorer::Custom(s.to_string()))
+            }
+            _ => Err(format_err!("invalid explorer URL template `{}`", s)),
+        }
+    }
+}
+
+/// Percent-encodes a query parameter value.
+fn encode_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+impl Explorer {
+    /// A link to an address or transaction on a cluster with the RPC URL `rpc_url`.
+    ///
+    /// Returns [None] if the explorer does not support the cluster.
+    pub fn link(
+        &self,
+        cluster: &ClusterRef,
+        rpc_url: &str,
+        kind: LinkKind,
+        id: &str,
+    ) -> Option<String> {
+        let custom_url = format!("cluster=custom&customUrl={}", encode_component(rpc_url));
+        let query = match (self, cluster) {
+            (Explorer::Custom(template), _) => {
+                return Some(
+                    template
+                        .replace("{kind}", kind.path())
+                        .replace("{id}", id)
+                        .replace("{cluster}", &cluster.to_string())
+                        .replace("{url}", &encode_component(rpc_url)),
+                )
+            }
+            (Explorer::SolanaFm, ClusterRef::Builtin(cluster)) => match cluster {
+                Cluster::Mainnet => "cluster=mainnet-alpha".to_string(),
+                Cluster::Devnet => "cluster=devnet-solana".to_string(),
+                Cluster::Testnet => "cluster=testnet-solana".to_string(),
+                Cluster::Localnet => "cluster=localnet-solana".to_string(),
+                _ => return None,
+            },
+            (Explorer::SolanaFm, ClusterRef::Named(_)) => return None,
+            (_, ClusterRef::Builtin(Cluster::Mainnet)) => String::new(),
+            (_, ClusterRef::Builtin(Cluster::Devnet)) => "cluster=devnet".to_string(),
+            (_, ClusterRef::Builtin(Cluster::Testnet)) => "cluster=testnet".to_string(),
+            _ => custom_url,
+        };
+        let (base, path) = match (self, kind) {
+            (Explorer::Solscan, LinkKind::Address) => ("https://solscan.io", "account"),
+            (Explorer::Solscan, LinkKind::Transaction) => ("https://solscan.io", "tx"),
+            (Explorer::SolanaFm, _) => ("https://solana.fm", kind.path()),
+            _ => ("https://explorer.solana.com", kind.path()),
+        };
+        let mut link = format!("{}/{}/{}", base, path, id);
+        if !query.is_empty() {
+            link.push('?');
+            link.push_str(&query);
+        }
+        Some(link)
+    }
+}
+
+impl Workspace {
+    /// The explorer configured for a cluster, Solana Explorer by default.
+    pub fn explorer(&self, cluster: &ClusterRef) -> Result<Explorer> {
+        match self.get_cluster(cluster)?.explorer {
+            Some(explorer) => explorer.parse(),
+            None => Ok(Explorer::SolanaExplorer),
+        }
+    }
+
+    /// A link to an address or transaction in the explorer of a cluster.
+    ///
+    /// Secrets in the RPC URL of the cluster are redacted, whether or not they are encoded.
+    pub fn explorer_link(&self, cluster: &ClusterRef, kind: LinkKind, id: &str) -> Option<String> {
+        let explorer = self.explorer(cluster).ok()?;
+        let rpc_url = self.get_cluster_url(cluster).ok()?;
+        explorer
+            .link(cluster, rpc_url, kind, id)
+            .map(|link| redact_encoded(&link, encode_component))
+    }
+
+    /// Prints explorer links for the transactions, programs and buffers in the output of
+    /// a `solana` command.
+    pub fn print_explorer_links(&self, cluster: &ClusterRef, output: &Output) {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for line in stdout.lines() {
+            let (label, kind, id) = match line.trim().split_once(": ") {
+                Some(("Signature", id)) => ("Transaction", LinkKind::Transaction, id),
+                Some(("Program Id", id)) => ("Program", LinkKind::Address, id),
+                Some(("Buffer", id)) => ("Buffer", LinkKind::Address, id),
+                _ => continue,
+            };
+            if let Some(link) = self.explorer_link(cluster, kind, id.trim()) {
+                println!("{} {}", format!("=> {}:", label).bold(), link);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "11111111111111111111111111111111";
+
+    fn link(explorer: &str, cluster: ClusterRef, rpc_url: &str, kind: LinkKind) -> Option<String> {
+        explorer
+            .parse::<Explorer>()
+            .unwrap()
+            .link(&cluster, rpc_url, kind, ID)
+    }
+
+    #[test]
+    fn parses_presets_and_templates() {
+        assert_eq!(
+            "solana-explorer".parse::<Explorer>().unwrap(),
+            Explorer::SolanaExplorer
+        );
+        assert_eq!("solscan".parse::<Explorer>().unwrap(), Explorer::Solscan);
+        assert_eq!("solanafm".parse::<Explorer>().unwrap(), Explorer::SolanaFm);
+        assert_eq!(
+            "https://x.io/{kind}/{id}".parse::<Explorer>().unwrap(),
+            Explorer::Custom("https://x.io/{kind}/{id}".to_string())
+        );
+        assert!("etherscan".parse::<Explorer>().is_err());
+        assert!("https://x.io/{kind}".parse::<Explorer>().is_err());
+        assert!("ftp://x.io/{id}".parse::<Explorer>().is_err());
+        assert!("{id}".parse::<Explorer>().is_err());
+    }
+
+    #[test]
+    fn links_to_solana_explorer() {
+        assert_eq!(
+            link(
+                "solana-explorer",
+                Cluster::Mainnet.into(),
+                "",
+                LinkKind::Transaction
+            )
+            .unwrap(),
+            format!("https://explorer.solana.com/tx/{}", ID)
+        );
+        assert_eq!(
+            link(
+                "solana-explorer",
+                Cluster::Devnet.into(),
+                "",
+                LinkKind::Address
+            )
+            .unwrap(),
+            format!("https://explorer.solana.com/address/{}?cluster=devnet", ID)
+        );
+        assert_eq!(
+            link(
+                "solana-explorer",
+                ClusterRef::Named("private".to_string()),
+                "http://127.0.0.1:8899/?a=b",
+                LinkKind::Address
+            )
+            .unwrap(),
+            format!(
+                "https://explorer.solana.com/address/{}?cluster=custom&customUrl=http%3A%2F%2F127.0.0.1%3A8899%2F%3Fa%3Db",
+                ID
+            )
+        );
+    }
+
+    #[test]
+    fn links_to_solscan_accounts() {
+        assert_eq!(
+            link("solscan", Cluster::Testnet.into(), "", LinkKind::Address).unwrap(),
+            format!("https://solscan.io/account/{}?cluster=testnet", ID)
+        );
+    }
+
+    #[test]
+    fn links_to_solanafm_only_on_builtin_clusters() {
+        assert_eq!(
+            link(
+                "solanafm",
+                Cluster::Localnet.into(),
+                "",
+                LinkKind::Transaction
+            )
+            .unwrap(),
+            format!("https://solana.fm/tx/{}?cluster=localnet-solana", ID)
+        );
+        assert_eq!(
+            link(
+                "solanafm",
+                ClusterRef::Named("private".to_string()),
+                "http://127.0.0.1:8899",
+                LinkKind::Address
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn fills_in_custom_templates() {
+        assert_eq!(
+            link(
+                "https://x.io/{cluster}/{kind}/{id}?rpc={url}",
+                ClusterRef::Named("private".to_string()),
+                "http://h:1",
+                LinkKind::Transaction
+            )
+            .unwrap(),
+            format!("https://x.io/private/tx/{}?rpc=http%3A%2F%2Fh%3A1", ID)
+        );
+    }
+
+    #[test]
+    fn encodes_components() {
+        assert_eq!(encode_component("aZ09-_.~"), "aZ09-_.~");
+        assert_eq!(encode_component("a b/é"), "a%20b%2F%C3%A9");
+    }
+}
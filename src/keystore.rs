@@ -0,0 +1,364 @@
+//! Encrypted deployer keypairs.
+//!
+//! An encrypted keypair is stored next to where its plaintext keypair would be, as
+//! `<cluster>.enc.json`. The key is derived from a password with scrypt and the keypair is
+//! sealed with ChaCha20-Poly1305. The public key is stored in the clear, so that it can be
+//! shown without the password.
+//!
+//! The `solana` CLI cannot read encrypted keypairs, so they are unlocked into temporary
+//! files that only the current user can read. These are removed by [lock_all] when the
+//! command finishes.
+
+use anyhow::{format_err, Result};
+use chacha20poly1305::{
+    aead::{Aead, NewAead, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use data_encoding::HEXLOWER;
+use lazy_static::lazy_static;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{write_keypair, Keypair},
+    signer::Signer,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+use tempfile::TempPath;
+
+/// Extension of encrypted keypair files, in place of `json`.
+pub const KEYSTORE_EXTENSION: &str = "enc.json";
+
+/// Environment variable the password of encrypted keypairs is read from, if set.
+pub const PASSWORD_ENV: &str = "GOKI_KEYSTORE_PASSWORD";
+
+/// Environment variable the new password is read from by `goki keys change-password` and
+/// `goki keys rotate`, if set.
+pub const NEW_PASSWORD_ENV: &str = "GOKI_KEYSTORE_NEW_PASSWORD";
+
+const KEYSTORE_VERSION: u32 = 1;
+const CIPHER: &str = "chacha20poly1305";
+const KDF: &str = "scrypt";
+
+/// scrypt parameters of new keystores: 32 MiB of memory.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+lazy_static! {
+    /// Unlocked keypairs by the path of their keystore.
+    static ref UNLOCKED: Mutex<BTreeMap<PathBuf, TempPath>> = Mutex::new(BTreeMap::new());
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub name: String,
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+    /// Hex-encoded salt.
+    pub salt: String,
+}
+
+/// Data encrypted with a key derived from a password.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sealed {
+    pub kdf: KdfParams,
+    pub cipher: String,
+    /// Hex-encoded nonce.
+    pub nonce: String,
+    /// Hex-encoded encrypted data.
+    pub ciphertext: String,
+}
+
+/// An encrypted keypair.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub pubkey: String,
+    /// The keypair bytes, sealed with the public key as associated data.
+    #[serde(flatten)]
+    pub sealed: Sealed,
+}
+
+fn derive_key(password: &str, kdf: &KdfParams) -> Result<[u8; KEY_LEN]> {
+    if kdf.name != KDF {
+        return Err(format_err!(
+            "unsupported key derivation function {}",
+            kdf.name
+        ));
+    }
+    let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p)
+        .map_err(|e| format_err!("invalid scrypt parameters: {}", e))?;
+    let salt = decode_hex("salt", &kdf.salt)?;
+    let mut key = [0u8; KEY_LEN];
+    scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key)
+        .map_err(|e| format_err!("could not derive key: {}", e))?;
+    Ok(key)
+}
+
+fn decode_hex(name: &str, value: &str) -> Result<Vec<u8>> {
+    HEXLOWER
+        .decode(value.as_bytes())
+        .map_err(|e| format_err!("invalid {}: {}", name, e))
+}
+
+impl Sealed {
+    /// Encrypts `plaintext` with a password, authenticating `aad` along with it.
+    pub fn seal(plaintext: &[u8], password: &str, aad: &[u8]) -> Result<Sealed> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let kdf = KdfParams {
+            name: KDF.to_string(),
+            log_n: SCRYPT_LOG_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: HEXLOWER.encode(&salt),
+        };
+        let key = derive_key(password, &kdf)?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| format_err!("could not encrypt"))?;
+        Ok(Sealed {
+            kdf,
+            cipher: CIPHER.to_string(),
+            nonce: HEXLOWER.encode(&nonce),
+            ciphertext: HEXLOWER.encode(&ciphertext),
+        })
+    }
+
+    /// Decrypts the data, failing if the password is wrong or the data was tampered with.
+    pub fn open(&self, password: &str, aad: &[u8]) -> Result<Vec<u8>> {
+        if self.cipher != CIPHER {
+            return Err(format_err!("unsupported cipher {}", self.cipher));
+        }
+        let key = derive_key(password, &self.kdf)?;
+        let nonce = decode_hex("nonce", &self.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(format_err!("invalid nonce"));
+        }
+        let ciphertext = decode_hex("ciphertext", &self.ciphertext)?;
+        ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| format_err!("incorrect password"))
+    }
+}
+
+impl Keystore {
+    /// Encrypts a keypair with a password.
+    pub fn encrypt(keypair: &Keypair, password: &str) -> Result<Keystore> {
+        let pubkey = keypair.pubkey().to_string();
+        let sealed = Sealed::seal(&keypair.to_bytes(), password, pubkey.as_bytes())?;
+        Ok(Keystore {
+            version: KEYSTORE_VERSION,
+            pubkey,
+            sealed,
+        })
+    }
+
+    /// Decrypts the keypair, failing if the password is wrong.
+    pub fn decrypt(&self, password: &str) -> Result<Keypair> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(format_err!(
+                "unsupported keystore version {}; please upgrade goki",
+                self.version
+            ));
+        }
+        let bytes = self.sealed.open(password, self.pubkey.as_bytes())?;
+        let keypair =
+            Keypair::from_bytes(&bytes).map_err(|e| format_err!("invalid keypair: {}", e))?;
+        if keypair.pubkey() != self.pubkey()? {
+            return Err(format_err!(
+                "keystore is corrupt: the keypair does not match its public key"
+            ));
+        }
+        Ok(keypair)
+    }
+
+    /// The public key of the keypair, which is readable without the password.
+    pub fn pubkey(&self) -> Result<Pubkey> {
+        Pubkey::from_str(&self.pubkey)
+            .map_err(|e| format_err!("invalid public key in keystore: {}", e))
+    }
+
+    pub fn read(path: &Path) -> Result<Keystore> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format_err!("could not read {}: {}", path.display(), e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format_err!("invalid keystore {}: {}", path.display(), e))
+    }
+
+    /// Writes the keystore, readable only by the current user.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write_private_file(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+}
+
+/// Writes a file that only the current user can read.
+///
+/// The contents are written to a temporary file in the same directory, which then replaces
+/// `path`, so that a crash never leaves a truncated keypair behind.
+pub fn write_private_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let write_err = |e: io::Error| format_err!("could not write {}: {}", path.display(), e);
+    // Temporary files are created readable only by the current user.
+    let mut file = tempfile::Builder::new()
+        .prefix(".goki-")
+        .suffix(".tmp")
+        .tempfile_in(dir)
+        .map_err(write_err)?;
+    file.write_all(contents).map_err(write_err)?;
+    file.as_file().sync_all().map_err(write_err)?;
+    file.persist(path).map_err(|e| write_err(e.error))?;
+    Ok(())
+}
+
+/// Writes a keypair file that only the current user can read.
+pub fn write_private_keypair(path: &Path, keypair: &Keypair) -> Result<()> {
+    let mut contents = vec![];
+    write_keypair(keypair, &mut contents)
+        .map_err(|e| format_err!("could not write {}: {}", path.display(), e))?;
+    write_private_file(path, &contents)
+}
+
+/// The path of the encrypted keypair corresponding to a plaintext keypair path.
+pub fn keystore_path(keypair_path: &Path) -> PathBuf {
+    keypair_path.with_extension(KEYSTORE_EXTENSION)
+}
+
+/// Reads a password from `env`, or prompts for it.
+pub fn read_password(prompt: &str, env: &str) -> Result<String> {
+    if let Ok(password) = std::env::var(env) {
+        return Ok(password);
+    }
+    rpassword::read_password_from_tty(Some(prompt))
+        .map_err(|e| format_err!("could not read password: {}", e))
+}
+
+/// Reads a new password from `env`, or prompts for it twice.
+pub fn read_new_password(env: &str) -> Result<String> {
+    if let Ok(password) = std::env::var(env) {
+        return Ok(password);
+    }
+    let password = read_password("New password: ", env)?;
+    if password.is_empty() {
+        return Err(format_err!("password must not be empty"));
+    }
+    if read_password("Confirm new password: ", env)? != password {
+        return Err(format_err!("passwords do not match"));
+    }
+    Ok(password)
+}
+
+/// Unlocks an encrypted keypair into a temporary keypair file, returning its path.
+///
+/// Each keystore is only unlocked once per command.
+pub fn unlock(path: &Path) -> Result<PathBuf> {
+    let mut unlocked = UNLOCKED.lock().unwrap();
+    if let Some(temp_path) = unlocked.get(path) {
+        return Ok(temp_path.to_path_buf());
+    }
+    let keystore = Keystore::read(path)?;
+    let password = read_password(
+        &format!("Password for {} ({}): ", path.display(), keystore.pubkey),
+        PASSWORD_ENV,
+    )?;
+    let keypair = keystore
+        .decrypt(&password)
+        .map_err(|e| format_err!("could not unlock {}: {}", path.display(), e))?;
+
+    // Temporary files are only readable by the current user.
+    let mut file = tempfile::Builder::new()
+        .prefix("goki-deployer-")
+        .suffix(".json")
+        .tempfile()?;
+    write_keypair(&keypair, file.as_file_mut())
+        .map_err(|e| format_err!("could not write unlocked keypair: {}", e))?;
+    let temp_path = file.into_temp_path();
+    let result = temp_path.to_path_buf();
+    unlocked.insert(path.to_path_buf(), temp_path);
+    Ok(result)
+}
+
+/// Removes the temporary files of all unlocked keypairs.
+pub fn lock_all() {
+    UNLOCKED.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_sealed_data_only_with_the_password_and_associated_data() {
+        let sealed = Sealed::seal(b"secret", "password", b"aad").unwrap();
+        assert_eq!(sealed.open("password", b"aad").unwrap(), b"secret");
+        assert_eq!(
+            sealed.open("wrong", b"aad").unwrap_err().to_string(),
+            "incorrect password"
+        );
+        assert!(sealed.open("password", b"other").is_err());
+    }
+
+    #[test]
+    fn decrypts_keystores_and_checks_their_public_key() {
+        let keypair = Keypair::new();
+        let keystore = Keystore::encrypt(&keypair, "password").unwrap();
+        assert_eq!(keystore.pubkey().unwrap(), keypair.pubkey());
+        assert_eq!(
+            keystore.decrypt("password").unwrap().to_bytes(),
+            keypair.to_bytes()
+        );
+        assert!(keystore.decrypt("wrong").is_err());
+
+        // The public key is associated data, so it cannot be swapped.
+        let mut tampered = keystore;
+        tampered.pubkey = Keypair::new().pubkey().to_string();
+        assert!(tampered.decrypt("password").is_err());
+    }
+
+    #[test]
+    fn writes_private_files_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deployer.json");
+        fs::write(&path, "old").unwrap();
+        write_private_file(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // No temporary files are left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -3,9 +3,16 @@
 #[macro_use]
 pub mod macros;
 
+pub mod bundle;
+pub mod cache;
 pub mod cli;
+pub mod cluster;
 pub mod config;
+pub mod explorer;
+pub mod keystore;
 pub mod location;
+pub mod policy;
+pub mod program;
 pub mod solana_cmd;
 pub mod subcommands;
 pub mod utils;
@@ -0,0 +1,131 @@
+//! Builds programs from the local workspace.
+
+use anchor_client::Cluster;
+use anyhow::{format_err, Result};
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    cluster::ClusterRef,
+    utils::{exec_command, exec_command_with_output},
+    workspace::Workspace,
+};
+
+/// Finds the directory of the nearest `Anchor.toml`, starting from the current directory.
+fn find_anchor_root() -> Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    cwd.ancestors()
+        .find(|dir| dir.join("Anchor.toml").exists())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| format_err!("Anchor.toml not found in {} or its parents", cwd.display()))
+}
+
+/// Path of the `.so` that `anchor build` produces for a program.
+pub fn anchor_program_output(program: &str) -> Result<PathBuf> {
+    Ok(find_anchor_root()?
+        .join("target/deploy")
+        .join(format!("{}.so", program)))
+}
+
+/// Finds the `.so` of an Anchor program, running `anchor build` first if
+/// `build` is set or if the program has not been built yet.
+pub fn anchor_program_path(workspace: &Workspace, program: &str, build: bool) -> Result<PathBuf> {
+    let program_path = anchor_program_output(program)?;
+    if build || !program_path.exists() {
+        exec_command(
+            anchor_cmd!(workspace, &ClusterRef::from(Cluster::Localnet), "build")
+                .arg("--program-name")
+                .arg(program),
+        )?;
+    }
+    if !program_path.exists() {
+        return Err(format_err!(
+            "{} not found after running `anchor build`",
+            program_path.display()
+        ));
+    }
+    Ok(program_path)
+}
+
+#[derive(Deserialize)]
+struct CargoMetadata {
+    packages: Vec<CargoPackage>,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+    name: String,
+    manifest_path: PathBuf,
+    targets: Vec<CargoTarget>,
+}
+
+#[derive(Deserialize)]
+struct CargoTarget {
+    name: String,
+    crate_types: Vec<String>,
+}
+
+/// The Cargo subcommand used to build on-chain programs.
+///
+/// Newer Solana toolchains ship `cargo build-sbf`, which replaces `cargo build-bpf`.
+fn cargo_build_subcommand() -> &'static str {
+    let has_build_sbf = Command::new("cargo")
+        .args(["build-sbf", "--version"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if has_build_sbf {
+        "build-sbf"
+    } else {
+        "build-bpf"
+    }
+}
+
+/// Builds a crate in the current Cargo workspace and returns the path of its `.so`.
+pub fn build_cargo_program(package_name: &str) -> Result<PathBuf> {
+    let metadata: CargoMetadata =
+        serde_json::from_str(&exec_command_with_output(Command::new("cargo").args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--no-deps",
+        ]))?)?;
+    let normalized_name = package_name.replace('-', "_");
+    let package = metadata
+        .packages
+        .iter()
+        .find(|package| package.name.replace('-', "_") == normalized_name)
+        .ok_or_else(|| format_err!("crate {} not found in the Cargo workspace", package_name))?;
+    let lib_name = package
+        .targets
+        .iter()
+        .find(|target| target.crate_types.iter().any(|t| t == "cdylib"))
+        .map(|target| target.name.replace('-', "_"))
+        .ok_or_else(|| format_err!("crate {} does not have a cdylib target", package_name))?;
+
+    exec_command(
+        Command::new("cargo")
+            .arg(cargo_build_subcommand())
+            .arg("--manifest-path")
+            .arg(&package.manifest_path),
+    )?;
+
+    let program_path = metadata
+        .target_directory
+        .join("deploy")
+        .join(format!("{}.so", lib_name));
+    if !program_path.exists() {
+        return Err(format_err!(
+            "{} not found after building {}",
+            program_path.display(),
+            package_name
+        ));
+    }
+    Ok(program_path)
+}
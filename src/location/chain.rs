@@ -0,0 +1,75 @@
+//! Reads program bytecode from on-chain accounts.
+
+use anchor_client::solana_client::rpc_client::RpcClient;
+use anyhow::{format_err, Result};
+use colored::*;
+use solana_sdk::{
+    bpf_loader, bpf_loader_deprecated,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    pubkey::Pubkey,
+};
+
+use super::elf::elf_len;
+
+fn strip_metadata(data: &[u8], offset: usize) -> Result<&[u8]> {
+    data.get(offset..)
+        .ok_or_else(|| format_err!("account data is smaller than the loader header"))
+}
+
+/// Reads the program bytecode stored in a program, program data or buffer account.
+///
+/// The loader header and any trailing padding are removed, leaving the ELF.
+pub fn read_program_bytecode(client: &RpcClient, address: &Pubkey) -> Result<Vec<u8>> {
+    let account = client
+        .get_account(address)
+        .map_err(|e| format_err!("could not read account {}: {}", address, e))?;
+
+    let data = if account.owner == bpf_loader_upgradeable::id() {
+        let offset = match account.deserialize_data::<UpgradeableLoaderState>()? {
+            UpgradeableLoaderState::Program {
+                programdata_address,
+            } => {
+                let programdata = client.get_account(&programdata_address).map_err(|e| {
+                    format_err!(
+                        "could not read program data account {}: {}",
+                        programdata_address,
+                        e
+                    )
+                })?;
+                let offset = UpgradeableLoaderState::programdata_data_offset()?;
+                return trim_to_elf(strip_metadata(&programdata.data, offset)?);
+            }
+            UpgradeableLoaderState::ProgramData { .. } => {
+                UpgradeableLoaderState::programdata_data_offset()?
+            }
+            UpgradeableLoaderState::Buffer { .. } => UpgradeableLoaderState::buffer_data_offset()?,
+            UpgradeableLoaderState::Uninitialized => {
+                return Err(format_err!("account {} is not initialized", address));
+            }
+        };
+        strip_metadata(&account.data, offset)?
+    } else if account.owner == bpf_loader::id() || account.owner == bpf_loader_deprecated::id() {
+        &account.data
+    } else {
+        return Err(format_err!(
+            "account {} is not a program or buffer; it is owned by {}",
+            address,
+            account.owner
+        ));
+    };
+    trim_to_elf(data)
+}
+
+fn trim_to_elf(data: &[u8]) -> Result<Vec<u8>> {
+    match elf_len(data) {
+        Ok(len) => Ok(data[..len].to_vec()),
+        Err(err) => {
+            eprintln!(
+                "{} could not determine program length ({}); keeping all account data",
+                "Warning:".yellow(),
+                err
+            );
+            Ok(data.to_vec())
+        }
+    }
+}
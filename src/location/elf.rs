@@ -0,0 +1,353 @@
+//! Inspects ELF program binaries.
+
+use anyhow::{format_err, Result};
+use colored::*;
+use goblin::elf::{
+    header::ET_DYN,
+    section_header::{SHF_EXECINSTR, SHT_NOBITS},
+    Elf,
+};
+
+/// Computes the length of the ELF file at the start of `data`.
+///
+/// On-chain program accounts may be larger than the program they contain, so this
+/// is used to strip the trailing padding.
+pub fn elf_len(data: &[u8]) -> Result<usize> {
+    let elf = Elf::parse(data).map_err(|e| format_err!("invalid ELF: {}", e))?;
+    let header = &elf.header;
+    let section_headers_end =
+        header.e_shoff + u64::from(header.e_shnum) * u64::from(header.e_shentsize);
+    let program_headers_end =
+        header.e_phoff + u64::from(header.e_phnum) * u64::from(header.e_phentsize);
+    let sections_end = elf
+        .section_headers
+        .iter()
+        .filter(|section| section.sh_type != SHT_NOBITS)
+        .map(|section| section.sh_offset + section.sh_size)
+        .max()
+        .unwrap_or_default();
+    let segments_end = elf
+        .program_headers
+        .iter()
+        .map(|segment| segment.p_offset + segment.p_filesz)
+        .max()
+        .unwrap_or_default();
+
+    let len = [
+        section_headers_end,
+        program_headers_end,
+        sections_end,
+        segments_end,
+    ]
+    .into_iter()
+    .max()
+    .unwrap_or_default();
+    usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= data.len())
+        .ok_or_else(|| format_err!("ELF extends past the end of the data"))
+}
+
+/// Maximum size of a program, which is limited by the maximum size of an account.
+pub const MAX_PROGRAM_SIZE: usize = 10 * 1024 * 1024;
+
+/// ELF machine type of programs built for Solana's original BPF target.
+const EM_BPF: u16 = goblin::elf::header::EM_BPF;
+/// ELF machine type of programs built for the SBF target.
+const EM_SBF: u16 = 263;
+
+/// Name of the symbol the runtime calls into.
+const ENTRYPOINT_SYMBOL: &str = "entrypoint";
+
+/// The result of a single check performed by [validate_elf].
+#[derive(Clone, Debug)]
+pub struct ElfCheck {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// The results of validating a program binary.
+#[derive(Clone, Debug, Default)]
+pub struct ElfReport {
+    pub checks: Vec<ElfCheck>,
+}
+
+impl ElfReport {
+    fn check(&mut self, name: &'static str, passed: bool, detail: impl Into<String>) -> bool {
+        self.checks.push(ElfCheck {
+            name,
+            passed,
+            detail: detail.into(),
+        });
+        passed
+    }
+
+    /// Whether every check passed.
+    pub fn is_valid(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    pub fn print(&self) {
+        println!("{}", "ELF validation:".bold());
+        for check in self.checks.iter() {
+            if check.passed {
+                println!("  {} {}: {}", "✓".green(), check.name, check.detail);
+            } else {
+                println!("  {} {}: {}", "✗".red(), check.name, check.detail.red());
+            }
+        }
+    }
+}
+
+/// Checks that `data` looks like a program that can be deployed to Solana.
+pub fn validate_elf(data: &[u8]) -> ElfReport {
+    let mut report = ElfReport::default();
+
+    report.check(
+        "Size",
+        !data.is_empty() && data.len() <= MAX_PROGRAM_SIZE,
+        format!("{} bytes (maximum {})", data.len(), MAX_PROGRAM_SIZE),
+    );
+
+    let has_magic = data.starts_with(b"\x7fELF");
+    let looks_like_text = data
+        .iter()
+        .take(512)
+        .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace());
+    let magic_detail = if has_magic {
+        "ELF magic found"
+    } else if looks_like_text {
+        "not an ELF file; it looks like text, such as an HTML error page"
+    } else {
+        "not an ELF file"
+    };
+    if !report.check("Magic", has_magic, magic_detail) {
+        return report;
+    }
+
+    let elf = match Elf::parse(data) {
+        Ok(elf) => elf,
+        Err(err) => {
+            report.check(
+                "Structure",
+                false,
+                format!("could not parse ELF, the file may be truncated: {}", err),
+            );
+            return report;
+        }
+    };
+
+    report.check(
+        "Format",
+        elf.is_64 && elf.little_endian && elf.header.e_type == ET_DYN,
+        format!(
+            "{}, {}, {}",
+            if elf.is_64 { "64-bit" } else { "32-bit" },
+            if elf.little_endian {
+                "little-endian"
+            } else {
+                "big-endian"
+            },
+            if elf.header.e_type == ET_DYN {
+                "shared object"
+            } else {
+                "not a shared object"
+            }
+        ),
+    );
+
+    let machine = elf.header.e_machine;
+    report.check(
+        "Machine",
+        machine == EM_BPF || machine == EM_SBF,
+        match machine {
+            EM_BPF => "BPF".to_string(),
+            EM_SBF => "SBF".to_string(),
+            _ => format!("unsupported machine type {}", machine),
+        },
+    );
+
+    let out_of_bounds = elf
+        .section_headers
+        .iter()
+        .filter(|section| section.sh_type != SHT_NOBITS)
+        .filter(|section| {
+            !matches!(
+                section.sh_offset.checked_add(section.sh_size),
+                Some(end) if end <= data.len() as u64
+            )
+        })
+        .count();
+    let has_text = elf.section_headers.iter().any(|section| {
+        elf.shdr_strtab.get_at(section.sh_name) == Some(".text")
+            && section.sh_flags & u64::from(SHF_EXECINSTR) != 0
+    });
+    report.check(
+        "Sections",
+        out_of_bounds == 0 && has_text,
+        if out_of_bounds > 0 {
+            format!(
+                "{} sections extend past the end of the file; the file may be truncated",
+                out_of_bounds
+            )
+        } else if !has_text {
+            "no executable .text section".to_string()
+        } else {
+            format!("{} sections", elf.section_headers.len())
+        },
+    );
+
+    let has_entrypoint = elf
+        .dynsyms
+        .iter()
+        .filter_map(|sym| elf.dynstrtab.get_at(sym.st_name))
+        .chain(
+            elf.syms
+                .iter()
+                .filter_map(|sym| elf.strtab.get_at(sym.st_name)),
+        )
+        .any(|name| name == ENTRYPOINT_SYMBOL);
+    report.check(
+        "Entrypoint",
+        has_entrypoint,
+        if has_entrypoint {
+            format!("`{}` symbol found", ENTRYPOINT_SYMBOL)
+        } else {
+            format!("`{}` symbol not found", ENTRYPOINT_SYMBOL)
+        },
+    );
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn align(data: &mut Vec<u8>) {
+        data.resize(data.len() + (8 - data.len() % 8) % 8, 0);
+    }
+
+    /// Builds a minimal shared object with an executable `.text` section and a symbol table
+    /// containing `symbol`.
+    fn build_elf(machine: u16, symbol: &str) -> Vec<u8> {
+        let mut data = vec![0; 64];
+        let text_offset = data.len();
+        // `exit`
+        data.extend_from_slice(&[0x95, 0, 0, 0, 0, 0, 0, 0]);
+        let strtab_offset = data.len();
+        let strtab = format!("\0{}\0", symbol);
+        data.extend_from_slice(strtab.as_bytes());
+        align(&mut data);
+        let symtab_offset = data.len();
+        data.extend_from_slice(&[0; 24]);
+        data.extend_from_slice(&1u32.to_le_bytes()); // st_name
+        data.push(0x12); // global function
+        data.push(0); // st_other
+        data.extend_from_slice(&1u16.to_le_bytes()); // st_shndx
+        data.extend_from_slice(&0u64.to_le_bytes()); // st_value
+        data.extend_from_slice(&8u64.to_le_bytes()); // st_size
+        let shstrtab_offset = data.len();
+        let shstrtab = "\0.text\0.strtab\0.symtab\0.shstrtab\0";
+        data.extend_from_slice(shstrtab.as_bytes());
+        align(&mut data);
+        let shoff = data.len();
+
+        // (name, type, flags, offset, size, link, entsize)
+        let sections = [
+            (0, 0, 0, 0, 0, 0, 0),
+            (1, 1, 6, text_offset, 8, 0, 0),
+            (7, 3, 0, strtab_offset, strtab.len(), 0, 0),
+            (15, 2, 0, symtab_offset, 48, 2, 24),
+            (23, 3, 0, shstrtab_offset, shstrtab.len(), 0, 0),
+        ];
+        for (name, kind, flags, offset, size, link, entsize) in sections {
+            data.extend_from_slice(&(name as u32).to_le_bytes());
+            data.extend_from_slice(&(kind as u32).to_le_bytes());
+            data.extend_from_slice(&(flags as u64).to_le_bytes());
+            data.extend_from_slice(&0u64.to_le_bytes()); // sh_addr
+            data.extend_from_slice(&(offset as u64).to_le_bytes());
+            data.extend_from_slice(&(size as u64).to_le_bytes());
+            data.extend_from_slice(&(link as u32).to_le_bytes());
+            data.extend_from_slice(&1u32.to_le_bytes()); // sh_info
+            data.extend_from_slice(&8u64.to_le_bytes()); // sh_addralign
+            data.extend_from_slice(&(entsize as u64).to_le_bytes());
+        }
+
+        let mut header = vec![0x7f, b'E', b'L', b'F', 2, 1, 1];
+        header.resize(16, 0);
+        header.extend_from_slice(&3u16.to_le_bytes()); // ET_DYN
+        header.extend_from_slice(&machine.to_le_bytes());
+        header.extend_from_slice(&1u32.to_le_bytes()); // e_version
+        header.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+        header.extend_from_slice(&0u64.to_le_bytes()); // e_phoff
+        header.extend_from_slice(&(shoff as u64).to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+        header.extend_from_slice(&64u16.to_le_bytes()); // e_ehsize
+        header.extend_from_slice(&56u16.to_le_bytes()); // e_phentsize
+        header.extend_from_slice(&0u16.to_le_bytes()); // e_phnum
+        header.extend_from_slice(&64u16.to_le_bytes()); // e_shentsize
+        header.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes()); // e_shstrndx
+        data[..64].copy_from_slice(&header);
+        data
+    }
+
+    fn failed_checks(report: &ElfReport) -> Vec<&'static str> {
+        report
+            .checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| check.name)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_programs() {
+        for machine in [EM_BPF, EM_SBF] {
+            let report = validate_elf(&build_elf(machine, ENTRYPOINT_SYMBOL));
+            assert!(report.is_valid(), "{:?}", report);
+            assert_eq!(report.checks.len(), 6);
+        }
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_programs() {
+        let report = validate_elf(b"<html><body>404 Not Found</body></html>");
+        assert_eq!(failed_checks(&report), vec!["Magic"]);
+        assert!(report.checks[1].detail.contains("HTML"));
+
+        assert_eq!(failed_checks(&validate_elf(b"")), vec!["Size", "Magic"]);
+
+        let mut too_large = build_elf(EM_BPF, ENTRYPOINT_SYMBOL);
+        too_large.resize(MAX_PROGRAM_SIZE + 1, 0);
+        assert_eq!(failed_checks(&validate_elf(&too_large)), vec!["Size"]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_foreign_binaries() {
+        let elf = build_elf(EM_BPF, ENTRYPOINT_SYMBOL);
+        assert!(!validate_elf(&elf[..elf.len() / 2]).is_valid());
+
+        // x86-64
+        assert_eq!(
+            failed_checks(&validate_elf(&build_elf(62, ENTRYPOINT_SYMBOL))),
+            vec!["Machine"]
+        );
+        assert_eq!(
+            failed_checks(&validate_elf(&build_elf(EM_BPF, "main"))),
+            vec!["Entrypoint"]
+        );
+    }
+
+    #[test]
+    fn computes_the_length_of_padded_programs() {
+        let elf = build_elf(EM_SBF, ENTRYPOINT_SYMBOL);
+        let mut padded = elf.clone();
+        padded.resize(elf.len() + 100, 0);
+        assert_eq!(elf_len(&padded).unwrap(), elf.len());
+        assert!(elf_len(&elf[..elf.len() - 1]).is_err());
+        assert!(elf_len(b"not an ELF file").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Resolves `gh:` locations through the GitHub Releases API.
+
+use anyhow::{format_err, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, USER_AGENT},
+    RequestBuilder, StatusCode,
+};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+
+/// Environment variable containing the GitHub token used for private repositories.
+pub const GITHUB_TOKEN_ENV: &str = "GITHUB_TOKEN";
+
+/// Number of releases to request per page when listing releases.
+const RELEASES_PER_PAGE: usize = 100;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ReleaseAsset {
+    pub name: String,
+    /// API URL of the asset, which works for private repositories.
+    pub url: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    /// Finds the `.so` asset of a program in the release.
+    pub fn find_program_asset(&self, program: &str) -> Result<&ReleaseAsset> {
+        let asset_name = format!("{}.so", program);
+        self.assets
+            .iter()
+            .find(|asset| asset.name == asset_name)
+            .ok_or_else(|| {
+                let available = if self.assets.is_empty() {
+                    "none".to_string()
+                } else {
+                    self.assets
+                        .iter()
+                        .map(|asset| asset.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                format_err!(
+                    "{} not found in release {}; available assets: {}",
+                    asset_name,
+                    self.tag_name,
+                    available
+                )
+            })
+    }
+}
+
+/// How a `gh:` version refers to a release.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReleaseRef {
+    /// The latest published release.
+    Latest,
+    /// An exact version, tagged either `v<version>` or `<version>`.
+    Exact(Version),
+    /// The highest published release matching a semver range.
+    Range(VersionReq),
+    /// A tag name that is not a version.
+    Tag(String),
+}
+
+fn parse_tag_version(tag: &str) -> Option<Version> {
+    Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()
+}
+
+impl ReleaseRef {
+    pub fn parse(version: &str) -> Self {
+        if version == "latest" {
+            ReleaseRef::Latest
+        } else if let Some(exact) = parse_tag_version(version) {
+            ReleaseRef::Exact(exact)
+        } else if let Ok(range) = VersionReq::parse(version) {
+            ReleaseRef::Range(range)
+        } else {
+            ReleaseRef::Tag(version.to_string())
+        }
+    }
+
+    /// Path of the GitHub API endpoint used to find the release.
+    pub fn api_path(&self, repo: &str) -> String {
+        match self {
+            ReleaseRef::Latest => format!("/repos/{}/releases/latest", repo),
+            ReleaseRef::Exact(exact) => format!("/repos/{}/releases/tags/v{}", repo, exact),
+            ReleaseRef::Range(_) => format!("/repos/{}/releases", repo),
+            ReleaseRef::Tag(tag) => format!("/repos/{}/releases/tags/{}", repo, tag),
+        }
+    }
+
+    /// Whether the release this refers to may change over time.
+    pub fn is_floating(&self) -> bool {
+        matches!(self, ReleaseRef::Latest | ReleaseRef::Range(_))
+    }
+}
+
+/// Client for the GitHub Releases API.
+pub struct GitHubClient {
+    client: reqwest::Client,
+    api_url: String,
+    token: Option<String>,
+}
+
+impl GitHubClient {
+    /// Creates a client for the API at `api_url`, authenticating with `GITHUB_TOKEN` if set.
+    pub fn new(api_url: &str, client: &reqwest::Client) -> Self {
+        Self {
+            client: client.clone(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            token: std::env::var(GITHUB_TOKEN_ENV)
+                .ok()
+                .filter(|token| !token.is_empty()),
+        }
+    }
+
+    fn auth_headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("goki-cli"));
+        if let Some(token) = &self.token {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", token))?,
+            );
+        }
+        Ok(headers)
+    }
+
+    fn get(&self, path: &str) -> Result<RequestBuilder> {
+        Ok(self
+            .client
+            .get(format!("{}{}", self.api_url, path))
+            .headers(self.auth_headers()?)
+            .header(ACCEPT, "application/vnd.github+json"))
+    }
+
+    async fn get_release(&self, path: &str) -> Result<Option<Release>> {
+        let response = self.get(path)?.send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(format_err!(
+                "GitHub API request {} failed with status {}",
+                path,
+                response.status()
+            ));
+        }
+        Ok(Some(response.json().await?))
+    }
+
+    /// Lists the published releases of a repository.
+    pub async fn list_releases(&self, repo: &str) -> Result<Vec<Release>> {
+        let mut releases: Vec<Release> = vec![];
+        for page in 1.. {
+            let path = format!(
+                "/repos/{}/releases?per_page={}&page={}",
+                repo, RELEASES_PER_PAGE, page
+            );
+            let response = self.get(&path)?.send().await?;
+            if !response.status().is_success() {
+                return Err(format_err!(
+                    "could not list releases of {}: status {}",
+                    repo,
+                    response.status()
+                ));
+            }
+            let page_releases: Vec<Release> = response.json().await?;
+            let is_last_page = page_releases.len() < RELEASES_PER_PAGE;
+            releases.extend(page_releases);
+            if is_last_page {
+                break;
+            }
+        }
+        Ok(releases
+            .into_iter()
+            .filter(|release| !release.draft)
+            .collect())
+    }
+
+    /// Finds the release of `repo` referred to by `version`.
+    pub async fn resolve_release(&self, repo: &str, version: &str) -> Result<Release> {
+        let release = match ReleaseRef::parse(version) {
+            ReleaseRef::Latest => {
+                self.get_release(&format!("/repos/{}/releases/latest", repo))
+                    .await?
+            }
+            ReleaseRef::Exact(exact) => {
+                let mut release = None;
+                for tag in [format!("v{}", exact), exact.to_string()] {
+                    release = self
+                        .get_release(&format!("/repos/{}/releases/tags/{}", repo, tag))
+                        .await?;
+                    if release.is_some() {
+                        break;
+                    }
+                }
+                release
+            }
+            ReleaseRef::Range(range) => self
+                .list_releases(repo)
+                .await?
+                .into_iter()
+                .filter(|release| !release.prerelease)
+                .filter_map(|release| {
+                    let version = parse_tag_version(&release.tag_name)?;
+                    if range.matches(&version) {
+                        Some((version, release))
+                    } else {
+                        None
+                    }
+                })
+                .max_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, release)| release),
+            ReleaseRef::Tag(tag) => {
+                self.get_release(&format!("/repos/{}/releases/tags/{}", repo, tag))
+                    .await?
+            }
+        };
+        release.ok_or_else(|| format_err!("no release of {} matches {}", repo, version))
+    }
+
+    /// The URL and headers to download a release asset with.
+    ///
+    /// Authenticated downloads go through the API, since browser download URLs
+    /// do not work for private repositories.
+    pub fn asset_download(&self, asset: &ReleaseAsset) -> Result<(String, HeaderMap)> {
+        let mut headers = self.auth_headers()?;
+        if self.token.is_some() {
+            headers.insert(ACCEPT, HeaderValue::from_static("application/octet-stream"));
+            Ok((asset.url.clone(), headers))
+        } else {
+            Ok((asset.browser_download_url.clone(), headers))
+        }
+    }
+}
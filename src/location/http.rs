@@ -0,0 +1,616 @@
+//! HTTP client used to download program binaries.
+
+use anyhow::{format_err, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_RANGE, RANGE},
+    Proxy, Response, StatusCode, Url,
+};
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    time::Duration,
+};
+use tokio::time::{sleep, timeout};
+
+use crate::{config::Http, workspace::Workspace};
+
+/// Longest time to wait between two attempts of a download.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+fn new_download_progress_bar() -> ProgressBar {
+    let progress = ProgressBar::new(0);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            )
+            .progress_chars("#>-"),
+    );
+    progress
+}
+
+/// Whether a request that failed with `status` may succeed if retried.
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+/// The length of the complete resource, from the `Content-Range: bytes */<length>` header of
+/// a response to a Range request that could not be satisfied.
+fn complete_length(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes */")?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// The first byte of a response to a Range request, from its
+/// `Content-Range: bytes <start>-<end>/<length>` header.
+fn range_start(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Empties a partially downloaded file.
+fn discard_partial_download(program_file: &mut File) -> Result<()> {
+    program_file.set_len(0)?;
+    program_file.seek(SeekFrom::Start(0))?;
+    Ok(())
+}
+
+/// Client that applies the `[http]` section of `Goki.toml` to every request.
+pub struct HttpClient {
+    client: reqwest::Client,
+    cfg: Http,
+}
+
+impl HttpClient {
+    pub fn new(cfg: &Http) -> Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(cfg.connect_timeout_secs));
+        if let Some(proxy) = &cfg.proxy {
+            builder = builder.proxy(
+                Proxy::all(proxy).map_err(|e| format_err!("invalid proxy {}: {}", proxy, e))?,
+            );
+        }
+        Ok(Self {
+            client: builder.build()?,
+            cfg: cfg.clone(),
+        })
+    }
+
+    /// The underlying [reqwest::Client].
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Headers configured for the host of `url` in `[http.auth]`.
+    ///
+    /// The token is read from the environment variable named in the config, so
+    /// that secrets do not need to be committed to `Goki.toml`.
+    pub fn host_headers(&self, url: &str) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        let host = match Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+        {
+            Some(host) => host,
+            None => return Ok(headers),
+        };
+        let auth = match self.cfg.auth.get(&host) {
+            Some(auth) => auth,
+            None => return Ok(headers),
+        };
+        let token = std::env::var(&auth.token_env).map_err(|_| {
+            format_err!(
+                "environment variable {} must be set to authenticate with {}",
+                auth.token_env,
+                host
+            )
+        })?;
+        match &auth.header {
+            Some(header) => {
+                headers.insert(
+                    HeaderName::from_bytes(header.as_bytes())?,
+                    HeaderValue::from_str(&token)?,
+                );
+            }
+            None => {
+                headers.insert(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&format!("Bearer {}", token))?,
+                );
+            }
+        }
+        Ok(headers)
+    }
+
+    /// How long to wait before retrying after `attempt` failed attempts.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = Duration::from_millis(self.cfg.backoff_ms)
+            .checked_mul(1 << attempt.saturating_sub(1).min(16))
+            .unwrap_or(MAX_BACKOFF);
+        backoff.min(MAX_BACKOFF)
+    }
+
+    /// Waits before the next attempt, or returns `false` if no retries are left.
+    ///
+    /// The reason is printed above `progress`, if there is one.
+    async fn retry(
+        &self,
+        progress: Option<&ProgressBar>,
+        attempt: u32,
+        reason: impl Display,
+    ) -> bool {
+        if attempt > self.cfg.retries {
+            return false;
+        }
+        let backoff = self.backoff(attempt);
+        let message = format!(
+            "{}; retrying in {:.1}s ({}/{})",
+            reason,
+            backoff.as_secs_f32(),
+            attempt,
+            self.cfg.retries
+        );
+        match progress {
+            Some(progress) => progress.println(message),
+            None => println!("{}", message),
+        }
+        sleep(backoff).await;
+        true
+    }
+
+    /// Sends a GET request to `url`, retrying dropped connections, timeouts and transient
+    /// errors with exponential backoff.
+    ///
+    /// Other unsuccessful responses are returned for the caller to handle.
+    pub async fn get(&self, url: &str) -> Result<Response> {
+        let headers = self.host_headers(url)?;
+        let read_timeout = Duration::from_secs(self.cfg.read_timeout_secs);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let request = self.client.get(url).headers(headers.clone()).send();
+            let reason = match timeout(read_timeout, request).await {
+                Ok(Ok(response)) if is_transient(response.status()) => {
+                    let reason = format!("Server returned {}", response.status());
+                    if !self.retry(None, attempt, &reason).await {
+                        return Ok(response);
+                    }
+                    continue;
+                }
+                Ok(Ok(response)) => return Ok(response),
+                Ok(Err(err)) => format!("Request to {} failed ({})", url, err),
+                Err(_) => format!("Request to {} timed out", url),
+            };
+            if !self.retry(None, attempt, &reason).await {
+                return Err(format_err!("{}", reason));
+            }
+        }
+    }
+
+    /// Downloads `target` into `program_file`, streaming the response body.
+    ///
+    /// If `program_file` already contains data, the download resumes from the end of
+    /// the file using an HTTP Range request. If the server reports that the resource has a
+    /// different length than the partial file, or responds with a different range than the
+    /// one requested, it is downloaded again from the start.
+    /// Dropped connections and transient errors are retried with exponential backoff,
+    /// resuming the same way.
+    pub async fn download(
+        &self,
+        program_file: &mut File,
+        target: &str,
+        headers: HeaderMap,
+    ) -> Result<()> {
+        println!("Downloading program code from {}", target);
+        let mut headers = headers;
+        headers.extend(self.host_headers(target)?);
+        let read_timeout = Duration::from_secs(self.cfg.read_timeout_secs);
+        let progress = new_download_progress_bar();
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut offset = program_file.seek(SeekFrom::End(0))?;
+
+            let mut request = self.client.get(target).headers(headers.clone());
+            if offset > 0 {
+                request = request.header(RANGE, format!("bytes={}-", offset));
+            }
+            let mut response = match timeout(read_timeout, request.send()).await {
+                Ok(Ok(response)) => response,
+                Ok(Err(err)) => {
+                    if self
+                        .retry(
+                            Some(&progress),
+                            attempt,
+                            format!("Request failed ({})", err),
+                        )
+                        .await
+                    {
+                        continue;
+                    }
+                    return Err(err.into());
+                }
+                Err(_) => {
+                    if self
+                        .retry(Some(&progress), attempt, "Request timed out")
+                        .await
+                    {
+                        continue;
+                    }
+                    return Err(format_err!("request to {} timed out", target));
+                }
+            };
+
+            let status = response.status();
+            if offset > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE {
+                if complete_length(&response) == Some(offset) {
+                    // Nothing left to download.
+                    break;
+                }
+                // The partial file is not a prefix of this download, so start from scratch.
+                progress.println(format!(
+                    "Partial download of {} bytes does not match {}; downloading it again",
+                    offset, target
+                ));
+                discard_partial_download(program_file)?;
+                continue;
+            }
+            if offset > 0
+                && status == StatusCode::PARTIAL_CONTENT
+                && range_start(&response) != Some(offset)
+            {
+                progress.println(format!(
+                    "Server did not resume {} at byte {}; downloading it again",
+                    target, offset
+                ));
+                discard_partial_download(program_file)?;
+                continue;
+            }
+            if is_transient(status)
+                && self
+                    .retry(
+                        Some(&progress),
+                        attempt,
+                        format!("Server returned {}", status),
+                    )
+                    .await
+            {
+                continue;
+            }
+            if !status.is_success() {
+                return Err(format_err!(
+                    "could not download program file: server returned {}",
+                    status
+                ));
+            }
+            if offset > 0 && status != StatusCode::PARTIAL_CONTENT {
+                // The server does not support ranges, so start from scratch.
+                discard_partial_download(program_file)?;
+                offset = 0;
+            }
+            if let Some(remaining) = response.content_length() {
+                progress.set_length(offset + remaining);
+            }
+            progress.set_position(offset);
+
+            let finished = loop {
+                let interruption = match timeout(read_timeout, response.chunk()).await {
+                    Ok(Ok(Some(chunk))) => {
+                        program_file.write_all(&chunk)?;
+                        progress.inc(chunk.len() as u64);
+                        continue;
+                    }
+                    Ok(Ok(None)) => break true,
+                    Ok(Err(err)) => err.to_string(),
+                    Err(_) => "timed out".to_string(),
+                };
+                let reason = format!(
+                    "Download interrupted ({}) at byte {}",
+                    interruption,
+                    progress.position()
+                );
+                if self.retry(Some(&progress), attempt, &reason).await {
+                    break false;
+                }
+                return Err(format_err!("{}", reason));
+            };
+            if finished {
+                break;
+            }
+        }
+
+        program_file.flush()?;
+        progress.finish();
+        Ok(())
+    }
+
+    /// Downloads from each URL in turn until one succeeds.
+    ///
+    /// Each URL is downloaded from the start, since a partial download from one URL may
+    /// not be a prefix of what another serves.
+    pub async fn download_with_fallback(
+        &self,
+        program_file: &mut File,
+        urls: &[String],
+    ) -> Result<()> {
+        for url in urls.iter() {
+            discard_partial_download(program_file)?;
+            match self.download(program_file, url, HeaderMap::new()).await {
+                Ok(()) => return Ok(()),
+                Err(err) => println!("Could not download from {}: {}", url, err),
+            }
+        }
+        Err(format_err!(
+            "could not download program from any of: {}",
+            urls.join(", ")
+        ))
+    }
+}
+
+impl Workspace {
+    /// The [HttpClient] configured by this workspace.
+    pub fn http_client(&self) -> Result<HttpClient> {
+        HttpClient::new(&self.cfg.http)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Read,
+        sync::{Arc, Mutex},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    /// Returns the raw response to the `n`th request, given the start of its Range header.
+    type Handler = Box<dyn Fn(usize, Option<u64>) -> Vec<u8> + Send>;
+
+    /// Serves requests on a local port with `handler`, returning the URL of the server and
+    /// the start of the Range header of every request it receives.
+    async fn serve(handler: Handler) -> (String, Arc<Mutex<Vec<Option<u64>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/program.so", listener.local_addr().unwrap());
+        let ranges = Arc::new(Mutex::new(vec![]));
+        let received = ranges.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let len = socket.read(&mut buf).await.unwrap();
+                    if len == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..len]);
+                }
+                let range = String::from_utf8_lossy(&request)
+                    .to_lowercase()
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes=")?.strip_suffix('-'))
+                    .and_then(|start| start.parse().ok());
+                let n = {
+                    let mut received = received.lock().unwrap();
+                    received.push(range);
+                    received.len()
+                };
+                socket.write_all(&handler(n, range)).await.ok();
+                socket.shutdown().await.ok();
+            }
+        });
+        (url, ranges)
+    }
+
+    /// A response whose body may be cut short of `content_length`.
+    fn response(
+        status: &str,
+        header: Option<String>,
+        body: &[u8],
+        content_length: usize,
+    ) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            status, content_length
+        );
+        if let Some(header) = header {
+            response.push_str(&header);
+            response.push_str("\r\n");
+        }
+        response.push_str("\r\n");
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    fn ok(body: &[u8]) -> Vec<u8> {
+        response("200 OK", None, body, body.len())
+    }
+
+    fn partial(start: usize) -> Vec<u8> {
+        response(
+            "206 Partial Content",
+            Some(format!(
+                "Content-Range: bytes {}-{}/{}",
+                start,
+                BODY.len() - 1,
+                BODY.len()
+            )),
+            &BODY[start..],
+            BODY.len() - start,
+        )
+    }
+
+    fn client(retries: u32) -> HttpClient {
+        HttpClient::new(&Http {
+            read_timeout_secs: 5,
+            retries,
+            backoff_ms: 1,
+            ..Http::default()
+        })
+        .unwrap()
+    }
+
+    fn file_with(contents: &[u8]) -> File {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(contents).unwrap();
+        file
+    }
+
+    fn contents(file: &mut File) -> Vec<u8> {
+        let mut contents = vec![];
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut contents).unwrap();
+        contents
+    }
+
+    #[tokio::test]
+    async fn resumes_partial_downloads() {
+        let (url, ranges) = serve(Box::new(|_, range| match range {
+            Some(start) => partial(start as usize),
+            None => ok(BODY),
+        }))
+        .await;
+        let mut file = file_with(&BODY[..10]);
+        client(0)
+            .download(&mut file, &url, HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(contents(&mut file), BODY);
+        assert_eq!(*ranges.lock().unwrap(), vec![Some(10)]);
+    }
+
+    #[tokio::test]
+    async fn restarts_when_the_server_sends_another_range() {
+        let (url, ranges) = serve(Box::new(|_, range| match range {
+            Some(_) => partial(0),
+            None => ok(BODY),
+        }))
+        .await;
+        let mut file = file_with(&BODY[..10]);
+        client(0)
+            .download(&mut file, &url, HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(contents(&mut file), BODY);
+        assert_eq!(*ranges.lock().unwrap(), vec![Some(10), None]);
+    }
+
+    #[tokio::test]
+    async fn accepts_416_only_for_complete_downloads() {
+        let handler = || -> Handler {
+            Box::new(|_, range| match range {
+                Some(_) => response(
+                    "416 Range Not Satisfiable",
+                    Some(format!("Content-Range: bytes */{}", BODY.len())),
+                    b"",
+                    0,
+                ),
+                None => ok(BODY),
+            })
+        };
+
+        let (url, ranges) = serve(handler()).await;
+        let mut file = file_with(BODY);
+        client(0)
+            .download(&mut file, &url, HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(contents(&mut file), BODY);
+        assert_eq!(*ranges.lock().unwrap(), vec![Some(BODY.len() as u64)]);
+
+        let (url, ranges) = serve(handler()).await;
+        let mut file = file_with(b"stale");
+        client(0)
+            .download(&mut file, &url, HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(contents(&mut file), BODY);
+        assert_eq!(*ranges.lock().unwrap(), vec![Some(5), None]);
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let handler = || -> Handler {
+            Box::new(|n, _| match n {
+                1 => response("503 Service Unavailable", None, b"", 0),
+                _ => ok(BODY),
+            })
+        };
+
+        let (url, ranges) = serve(handler()).await;
+        let mut file = file_with(b"");
+        client(1)
+            .download(&mut file, &url, HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(contents(&mut file), BODY);
+        assert_eq!(ranges.lock().unwrap().len(), 2);
+
+        let (url, ranges) = serve(handler()).await;
+        let mut file = file_with(b"");
+        assert!(client(0)
+            .download(&mut file, &url, HeaderMap::new())
+            .await
+            .is_err());
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let client = HttpClient::new(&Http {
+            backoff_ms: 500,
+            ..Http::default()
+        })
+        .unwrap();
+        assert_eq!(client.backoff(1), Duration::from_millis(500));
+        assert_eq!(client.backoff(2), Duration::from_secs(1));
+        assert_eq!(client.backoff(3), Duration::from_secs(2));
+        assert_eq!(client.backoff(10), MAX_BACKOFF);
+        assert_eq!(client.backoff(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_mirrors_from_the_start() {
+        let other = b"another binary served by the mirror";
+        // The primary host drops the connection partway through.
+        let (primary, _) = serve(Box::new(|_, _| {
+            response("200 OK", None, &BODY[..10], BODY.len())
+        }))
+        .await;
+        let (mirror, mirror_ranges) = serve(Box::new(move |_, _| ok(other))).await;
+        let mut file = file_with(b"");
+        client(0)
+            .download_with_fallback(&mut file, &[primary, mirror])
+            .await
+            .unwrap();
+        assert_eq!(contents(&mut file), other);
+        assert_eq!(*mirror_ranges.lock().unwrap(), vec![None]);
+    }
+}
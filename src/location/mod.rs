@@ -0,0 +1,387 @@
+//! Parses and fetches program locations.
+use anyhow::{format_err, Result};
+use colored::*;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    fs::File,
+    io::{copy, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{cluster::ClusterRef, utils::sha256_digest, workspace::Workspace};
+
+pub mod build;
+pub mod chain;
+pub mod elf;
+pub mod github;
+pub mod http;
+mod parse;
+pub mod registry;
+
+pub use self::elf::{validate_elf, ElfReport};
+pub use self::parse::{parse_spr_ref, split_checksum, LocationError};
+
+use self::github::{GitHubClient, ReleaseRef};
+use self::http::HttpClient;
+use self::parse::parse_sha256;
+use self::registry::RegistryIndex;
+
+fn gateway_urls(gateways: &[String], id: &str) -> Vec<String> {
+    gateways
+        .iter()
+        .map(|gateway| format!("{}/{}", gateway.trim_end_matches('/'), id))
+        .collect()
+}
+
+/// Downloads the `.so` asset of `program` from the release of `repo` referred to by `version`.
+async fn download_release_asset(
+    github: &GitHubClient,
+    http: &HttpClient,
+    program_file: &mut File,
+    program: &str,
+    repo: &str,
+    version: &str,
+) -> Result<()> {
+    let release = github.resolve_release(repo, version).await?;
+    println!(
+        "Resolved {}@{} to release {}",
+        repo,
+        version,
+        release.tag_name.bold()
+    );
+    let asset = release.find_program_asset(program)?;
+    let (target, headers) = github.asset_download(asset)?;
+    http.download(program_file, &target, headers).await
+}
+
+/// Options for fetching a program binary.
+#[derive(Clone, Debug, Default, PartialEq, Eq, clap::Args)]
+pub struct FetchOptions {
+    /// The expected SHA256 checksum of the program binary, as hex.
+    ///
+    /// This may also be specified by appending `#sha256=<hex>` to the location.
+    #[clap(long)]
+    pub expected_sha256: Option<String>,
+
+    /// Fetches the program even if it is in the workspace cache.
+    ///
+    /// The fetched program is still added to the cache.
+    #[clap(long)]
+    pub no_cache: bool,
+
+    /// Rebuilds `anchor:` programs even if they have already been built.
+    #[clap(long)]
+    pub build: bool,
+
+    /// Skips checking that the program is a valid Solana ELF binary.
+    #[clap(long)]
+    pub skip_elf_check: bool,
+}
+
+/// Checks that `program_file` contains a valid program, printing a report of the checks.
+pub fn verify_elf(program_file: &mut File) -> Result<()> {
+    let mut data = vec![];
+    program_file.seek(SeekFrom::Start(0))?;
+    program_file.read_to_end(&mut data)?;
+    let report = validate_elf(&data);
+    report.print();
+    if !report.is_valid() {
+        return Err(format_err!(
+            "program binary failed ELF validation; pass --skip-elf-check to use it anyway"
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the contents of `program_file` hash to `expected`.
+pub fn verify_sha256(program_file: &mut File, expected: &str) -> Result<()> {
+    program_file.seek(SeekFrom::Start(0))?;
+    let (_, digest) = sha256_digest(program_file)?;
+    program_file.seek(SeekFrom::End(0))?;
+    if !digest.eq_ignore_ascii_case(expected) {
+        return Err(format_err!(
+            "checksum mismatch: expected SHA256 {}, got {}",
+            expected,
+            digest
+        ));
+    }
+    println!("{} {}", "Checksum verified:".bold(), digest.green());
+    Ok(())
+}
+
+fn copy_local_file(path: &Path, program_file: &mut File) -> Result<()> {
+    let mut file = File::open(path)
+        .map_err(|e| format_err!("could not open program file {}: {}", path.display(), e))?;
+    program_file.set_len(0)?;
+    program_file.seek(SeekFrom::Start(0))?;
+    copy(&mut file, program_file)?;
+    Ok(())
+}
+
+/// Program location.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Location {
+    SolanaProgramRegistry {
+        id: String,
+        version: String,
+    },
+    GitHub {
+        program: String,
+        repo: String,
+        version: String,
+    },
+    URL {
+        url: String,
+    },
+    Local {
+        path: PathBuf,
+    },
+    /// A program, program data or buffer account on a cluster.
+    Chain {
+        cluster: ClusterRef,
+        address: Pubkey,
+    },
+    /// A program in the nearest Anchor workspace.
+    Anchor {
+        program: String,
+    },
+    /// A crate in the current Cargo workspace.
+    Cargo {
+        package: String,
+    },
+    /// Content on IPFS, fetched through the configured gateways.
+    Ipfs {
+        cid: String,
+    },
+    /// Data of an Arweave transaction, fetched through the configured gateways.
+    Arweave {
+        tx_id: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArtifactMetadata {
+    pub id: String,
+    pub tag: String,
+    pub name: String,
+    pub source: String,
+    pub url: String,
+    pub checksum: String,
+}
+
+impl ArtifactMetadata {
+    pub fn print_info(&self) {
+        println!(
+            "{} {}{}{}",
+            "Artifact:".bold().blue(),
+            self.id.bold(),
+            "@".bold(),
+            self.tag.bold()
+        );
+        for [property, value] in [
+            ["ID", &self.id],
+            ["Tag", &self.tag],
+            ["Name", &self.name],
+            ["Source", &self.source],
+            ["URL", &self.url],
+            ["SHA256", &self.checksum],
+        ] {
+            println!("  {}: {}", property, value.green());
+        }
+    }
+}
+
+impl Location {
+    /// Whether the program at this [Location] can be cached by its location string.
+    ///
+    /// Local files and floating versions may change, so they are never cached.
+    pub fn is_cacheable(&self) -> bool {
+        match self {
+            Location::SolanaProgramRegistry { version, .. } => version != "latest",
+            Location::GitHub { version, .. } => !ReleaseRef::parse(version).is_floating(),
+            Location::URL { .. } | Location::Ipfs { .. } | Location::Arweave { .. } => true,
+            Location::Local { .. }
+            | Location::Chain { .. }
+            | Location::Anchor { .. }
+            | Location::Cargo { .. } => false,
+        }
+    }
+
+    /// Where the program at this [Location] is fetched from, without fetching it.
+    pub fn source(&self, workspace: &Workspace) -> Result<String> {
+        Ok(match self {
+            Location::SolanaProgramRegistry { id, version } => {
+                RegistryIndex::new(&workspace.cfg.registry.index_url).artifact_url(id, version)
+            }
+            Location::GitHub { repo, version, .. } => format!(
+                "{}{}",
+                workspace.cfg.github.api_url.trim_end_matches('/'),
+                ReleaseRef::parse(version).api_path(repo)
+            ),
+            Location::URL { url } => url.clone(),
+            Location::Local { path } => path.display().to_string(),
+            Location::Chain { cluster, .. } => workspace.get_cluster_url(cluster)?.to_string(),
+            Location::Anchor { program } => {
+                build::anchor_program_output(program)?.display().to_string()
+            }
+            Location::Cargo { package } => {
+                format!("crate {} in the current Cargo workspace", package)
+            }
+            Location::Ipfs { cid } => gateway_urls(&workspace.cfg.gateways.ipfs, cid).join(", "),
+            Location::Arweave { tx_id } => {
+                gateway_urls(&workspace.cfg.gateways.arweave, tx_id).join(", ")
+            }
+        })
+    }
+
+    /// Fetches the program file associated with the [Location].
+    ///
+    /// Remote files are resumed if `program_file` already contains a partial download.
+    pub async fn fetch_program_file(
+        self,
+        workspace: &Workspace,
+        program_file: &mut File,
+        options: &FetchOptions,
+    ) -> Result<()> {
+        let http = workspace.http_client()?;
+        let mirrors = workspace
+            .cfg
+            .mirrors
+            .get(&self.to_string())
+            .cloned()
+            .unwrap_or_default();
+        match self {
+            Location::SolanaProgramRegistry { id, version } => {
+                let index = RegistryIndex::new(&workspace.cfg.registry.index_url);
+                let metadata = index.get_artifact(&http, &id, &version).await?;
+                metadata.print_info();
+                println!();
+                if !index.copy_local_artifact(&metadata, program_file)? {
+                    http.download(program_file, &metadata.url, HeaderMap::new())
+                        .await?;
+                }
+                verify_sha256(program_file, &metadata.checksum)?;
+            }
+            Location::GitHub {
+                program,
+                repo,
+                version,
+            } => {
+                let github = GitHubClient::new(&workspace.cfg.github.api_url, http.client());
+                let result =
+                    download_release_asset(&github, &http, program_file, &program, &repo, &version)
+                        .await;
+                match result {
+                    Err(err) if !mirrors.is_empty() => {
+                        println!("Could not download from GitHub: {}", err);
+                        http.download_with_fallback(program_file, &mirrors).await?;
+                    }
+                    result => result?,
+                }
+            }
+            Location::URL { url } => {
+                let mut urls = vec![url];
+                urls.extend(mirrors);
+                http.download_with_fallback(program_file, &urls).await?;
+            }
+            Location::Local { path } => {
+                copy_local_file(&path, program_file)?;
+            }
+            Location::Anchor { program } => {
+                let path = build::anchor_program_path(workspace, &program, options.build)?;
+                copy_local_file(&path, program_file)?;
+            }
+            Location::Cargo { package } => {
+                let path = build::build_cargo_program(&package)?;
+                copy_local_file(&path, program_file)?;
+            }
+            Location::Chain { cluster, address } => {
+                println!(
+                    "Reading program bytecode of {} on {}",
+                    address,
+                    cluster.to_string().bold()
+                );
+                let bytecode =
+                    chain::read_program_bytecode(&workspace.rpc_client(&cluster)?, &address)?;
+                program_file.set_len(0)?;
+                program_file.seek(SeekFrom::Start(0))?;
+                program_file.write_all(&bytecode)?;
+            }
+            Location::Ipfs { cid } => {
+                let urls = gateway_urls(&workspace.cfg.gateways.ipfs, &cid);
+                http.download_with_fallback(program_file, &urls).await?;
+            }
+            Location::Arweave { tx_id } => {
+                let urls = gateway_urls(&workspace.cfg.gateways.arweave, &tx_id);
+                http.download_with_fallback(program_file, &urls).await?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Workspace {
+    /// Fetches a program from a location.
+    ///
+    /// If a checksum is pinned on the location or in the [FetchOptions], the program
+    /// file must match it. Remote binaries are served from the workspace's
+    /// [crate::cache::ArtifactCache] when possible.
+    pub async fn fetch_program_file(
+        &self,
+        program_file: &mut File,
+        location_str: &str,
+        options: &FetchOptions,
+    ) -> Result<()> {
+        let (location_str, pinned_sha256) = split_checksum(location_str)?;
+        let flag_sha256 = options
+            .expected_sha256
+            .as_deref()
+            .map(parse_sha256)
+            .transpose()?;
+        let expected_sha256 = match (pinned_sha256, flag_sha256) {
+            (Some(pinned), Some(flag)) if pinned != flag => {
+                return Err(format_err!(
+                    "checksum pinned on location ({}) does not match --expected-sha256 ({})",
+                    pinned,
+                    flag
+                ));
+            }
+            (pinned, flag) => pinned.or(flag),
+        };
+
+        let location = Location::try_from(location_str)?;
+        let cacheable = location.is_cacheable();
+        let cache = self.artifact_cache();
+        let cached = if options.no_cache || (!cacheable && expected_sha256.is_none()) {
+            None
+        } else {
+            cache.get(location_str, expected_sha256.as_deref())?
+        };
+
+        match cached {
+            Some(cached_path) => {
+                println!("Using cached program from {}", cached_path.display());
+                program_file.set_len(0)?;
+                program_file.seek(SeekFrom::Start(0))?;
+                copy(&mut File::open(&cached_path)?, program_file)?;
+            }
+            None => {
+                location
+                    .fetch_program_file(self, program_file, options)
+                    .await?;
+            }
+        }
+        if let Some(expected) = expected_sha256 {
+            verify_sha256(program_file, &expected)?;
+        }
+        if !options.skip_elf_check {
+            verify_elf(program_file)?;
+        }
+        if cacheable {
+            cache.insert(location_str, program_file)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+//! Parses and formats [Location]s.
+
+use solana_sdk::pubkey::Pubkey;
+use std::{fmt, path::PathBuf, str::FromStr};
+use thiserror::Error;
+
+use crate::cluster::ClusterRef;
+
+use super::Location;
+
+/// Suffix that pins a location to a checksum, e.g. `gh:smart_wallet:GokiProtocol/goki@0.5.2#sha256=<hex>`.
+const CHECKSUM_SUFFIX: &str = "#sha256=";
+
+/// Error parsing a [Location].
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum LocationError {
+    #[error("location is empty")]
+    Empty,
+    #[error("unknown location scheme `{0}:`; expected one of spr:, gh:, chain:, anchor:, cargo:, ipfs:, ar:, http:// or https://")]
+    UnknownScheme(String),
+    #[error(
+        "invalid gh location `{0}`: should look like `gh:smart_wallet:GokiProtocol/goki@0.5.2`"
+    )]
+    InvalidGitHub(String),
+    #[error("gh location `{0}` is missing a version: append `@<version>` or `@latest`")]
+    MissingVersion(String),
+    #[error("invalid spr location `{0}`: should look like `spr:QuarryProtocol/quarry_mine@0.1.0`")]
+    InvalidRegistry(String),
+    #[error("invalid chain location `{0}`: should look like `chain:mainnet:<program or buffer address>`")]
+    InvalidChain(String),
+    #[error("invalid cluster name `{0}`")]
+    InvalidCluster(String),
+    #[error("invalid account address `{0}`")]
+    InvalidAddress(String),
+    #[error("missing {0} name: should look like `{1}`")]
+    MissingName(&'static str, &'static str),
+    #[error("invalid content ID `{0}`")]
+    InvalidContentId(String),
+    #[error("invalid SHA256 checksum `{0}`: must be 64 hex characters")]
+    InvalidChecksum(String),
+}
+
+pub(crate) fn parse_sha256(checksum: &str) -> Result<String, LocationError> {
+    if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(LocationError::InvalidChecksum(checksum.to_string()));
+    }
+    Ok(checksum.to_ascii_lowercase())
+}
+
+/// Splits the `#sha256=<hex>` suffix from a location, if present.
+pub fn split_checksum(location: &str) -> Result<(&str, Option<String>), LocationError> {
+    match location.rsplit_once(CHECKSUM_SUFFIX) {
+        Some((location, checksum)) => Ok((location, Some(parse_sha256(checksum)?))),
+        None => Ok((location, None)),
+    }
+}
+
+/// Splits a Solana Program Registry reference into its ID and version, which defaults to `latest`.
+pub fn parse_spr_ref(raw_spr_ref: &str) -> (&str, &str) {
+    match raw_spr_ref.split_once('@') {
+        Some(s) => s,
+        None => (raw_spr_ref, "latest"),
+    }
+}
+
+/// The scheme of a location, if it looks like `<scheme>:...`.
+///
+/// Single letters are not schemes, so that Windows drive letters are treated as paths.
+fn scheme(location: &str) -> Option<&str> {
+    let (scheme, _) = location.split_once(':')?;
+    let mut chars = scheme.chars();
+    let is_scheme = scheme.len() > 1
+        && chars.next()?.is_ascii_alphabetic()
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    if is_scheme {
+        Some(scheme)
+    } else {
+        None
+    }
+}
+
+fn parse_gh_location(location: &str, raw_gh_ref: &str) -> Result<Location, LocationError> {
+    let invalid = || LocationError::InvalidGitHub(location.to_string());
+    let (program, rest) = raw_gh_ref.split_once(':').ok_or_else(invalid)?;
+    let (repo, version) = rest
+        .split_once('@')
+        .ok_or_else(|| LocationError::MissingVersion(location.to_string()))?;
+    let valid_repo = match repo.split_once('/') {
+        Some((owner, name)) => !owner.is_empty() && !name.is_empty() && !name.contains('/'),
+        None => false,
+    };
+    if program.is_empty() || !valid_repo {
+        return Err(invalid());
+    }
+    if version.is_empty() {
+        return Err(LocationError::MissingVersion(location.to_string()));
+    }
+    Ok(Location::GitHub {
+        program: program.to_string(),
+        repo: repo.to_string(),
+        version: version.to_string(),
+    })
+}
+
+fn parse_chain_location(location: &str, raw_chain_ref: &str) -> Result<Location, LocationError> {
+    let (cluster_str, address_str) = raw_chain_ref
+        .rsplit_once(':')
+        .ok_or_else(|| LocationError::InvalidChain(location.to_string()))?;
+    let cluster = ClusterRef::from_str(cluster_str)
+        .map_err(|_| LocationError::InvalidCluster(cluster_str.to_string()))?;
+    let address = Pubkey::from_str(address_str)
+        .map_err(|_| LocationError::InvalidAddress(address_str.to_string()))?;
+    Ok(Location::Chain { cluster, address })
+}
+
+/// Parses an IPFS CID (optionally followed by a path) or an Arweave transaction ID.
+fn parse_content_id(id: &str, example: &'static str) -> Result<String, LocationError> {
+    if id.is_empty() {
+        return Err(LocationError::MissingName("content ID", example));
+    }
+    let valid = id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '/' || c == '.');
+    if !valid {
+        return Err(LocationError::InvalidContentId(id.to_string()));
+    }
+    Ok(id.to_string())
+}
+
+impl FromStr for Location {
+    type Err = LocationError;
+
+    fn from_str(location: &str) -> Result<Self, Self::Err> {
+        if location.is_empty() {
+            return Err(LocationError::Empty);
+        }
+        if location.starts_with("https://") || location.starts_with("http://") {
+            return Ok(Location::URL {
+                url: location.to_string(),
+            });
+        }
+        if let Some(path) = location.strip_prefix("file://") {
+            return Ok(Location::Local {
+                path: PathBuf::from(path),
+            });
+        }
+
+        let scheme = match scheme(location) {
+            Some(scheme) => scheme,
+            None => {
+                return Ok(Location::Local {
+                    path: PathBuf::from(location),
+                })
+            }
+        };
+        let rest = &location[scheme.len() + 1..];
+        match scheme {
+            "spr" => {
+                let (id, version) = parse_spr_ref(rest);
+                if id.is_empty() || version.is_empty() {
+                    return Err(LocationError::InvalidRegistry(location.to_string()));
+                }
+                Ok(Location::SolanaProgramRegistry {
+                    id: id.to_string(),
+                    version: version.to_string(),
+                })
+            }
+            "gh" => parse_gh_location(location, rest),
+            "chain" => parse_chain_location(location, rest),
+            "anchor" => {
+                if rest.is_empty() {
+                    return Err(LocationError::MissingName("program", "anchor:smart_wallet"));
+                }
+                Ok(Location::Anchor {
+                    program: rest.to_string(),
+                })
+            }
+            "cargo" => {
+                if rest.is_empty() {
+                    return Err(LocationError::MissingName("crate", "cargo:smart-wallet"));
+                }
+                Ok(Location::Cargo {
+                    package: rest.to_string(),
+                })
+            }
+            "ipfs" => Ok(Location::Ipfs {
+                cid: parse_content_id(rest, "ipfs:<cid>")?,
+            }),
+            "ar" => Ok(Location::Arweave {
+                tx_id: parse_content_id(rest, "ar:<transaction id>")?,
+            }),
+            _ => Err(LocationError::UnknownScheme(scheme.to_string())),
+        }
+    }
+}
+
+impl TryFrom<&str> for Location {
+    type Error = anyhow::Error;
+
+    fn try_from(location: &str) -> anyhow::Result<Self> {
+        Ok(location.parse()?)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::SolanaProgramRegistry { id, version } => {
+                write!(f, "spr:{}@{}", id, version)
+            }
+            Location::GitHub {
+                program,
+                repo,
+                version,
+            } => write!(f, "gh:{}:{}@{}", program, repo, version),
+            Location::URL { url } => write!(f, "{}", url),
+            Location::Local { path } => {
+                let path = path.display().to_string();
+                // Paths that look like another kind of location must be escaped.
+                if scheme(&path).is_some() {
+                    write!(f, "file://{}", path)
+                } else {
+                    write!(f, "{}", path)
+                }
+            }
+            Location::Chain { cluster, address } => write!(f, "chain:{}:{}", cluster, address),
+            Location::Anchor { program } => write!(f, "anchor:{}", program),
+            Location::Cargo { package } => write!(f, "cargo:{}", package),
+            Location::Ipfs { cid } => write!(f, "ipfs:{}", cid),
+            Location::Arweave { tx_id } => write!(f, "ar:{}", tx_id),
+        }
+    }
+}
+
+impl Location {
+    /// A human-readable description of the kind of location.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Location::SolanaProgramRegistry { .. } => "Solana Program Registry artifact",
+            Location::GitHub { .. } => "GitHub release artifact",
+            Location::URL { .. } => "URL",
+            Location::Local { .. } => "Local file",
+            Location::Chain { .. } => "On-chain account",
+            Location::Anchor { .. } => "Anchor program",
+            Location::Cargo { .. } => "Cargo crate",
+            Location::Ipfs { .. } => "IPFS content",
+            Location::Arweave { .. } => "Arweave transaction",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_kind_of_location() {
+        for raw in [
+            "spr:QuarryProtocol/quarry_mine@0.1.0",
+            "gh:smart_wallet:GokiProtocol/goki@0.5.2",
+            "gh:smart_wallet:GokiProtocol/goki@^0.5",
+            "https://github.com/GokiProtocol/goki/releases/download/v0.5.2/smart_wallet.so",
+            "http://127.0.0.1:8080/program.so",
+            "./target/deploy/smart_wallet.so",
+            "chain:mainnet:GokivDYuQXPZCWRkwMhdH2h91KpDQXBEmpgBgs55bnpH",
+            "anchor:smart_wallet",
+            "cargo:smart-wallet",
+            "ipfs:bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi/program.so",
+            "ar:bNbA3TEQVL60xlgCcqdz4ZPHFZ711cZ3hmkpGttDt_U",
+        ] {
+            let location: Location = raw.parse().unwrap();
+            assert_eq!(location.to_string(), raw);
+            assert_eq!(location.to_string().parse::<Location>().unwrap(), location);
+        }
+    }
+
+    #[test]
+    fn defaults_spr_version_to_latest() {
+        assert_eq!(
+            "spr:QuarryProtocol/quarry_mine"
+                .parse::<Location>()
+                .unwrap(),
+            Location::SolanaProgramRegistry {
+                id: "QuarryProtocol/quarry_mine".to_string(),
+                version: "latest".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn escapes_paths_that_look_like_schemes() {
+        let location = Location::Local {
+            path: PathBuf::from("gh:not-a-location.so"),
+        };
+        assert_eq!(location.to_string(), "file://gh:not-a-location.so");
+        assert_eq!(location.to_string().parse::<Location>().unwrap(), location);
+    }
+
+    #[test]
+    fn treats_drive_letters_as_paths() {
+        assert_eq!(
+            r"C:\programs\smart_wallet.so".parse::<Location>().unwrap(),
+            Location::Local {
+                path: PathBuf::from(r"C:\programs\smart_wallet.so"),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_locations() {
+        assert_eq!("".parse::<Location>(), Err(LocationError::Empty));
+        assert_eq!(
+            "foo:bar".parse::<Location>(),
+            Err(LocationError::UnknownScheme("foo".to_string()))
+        );
+        assert_eq!(
+            "gh:smart_wallet:GokiProtocol/goki".parse::<Location>(),
+            Err(LocationError::MissingVersion(
+                "gh:smart_wallet:GokiProtocol/goki".to_string()
+            ))
+        );
+        assert_eq!(
+            "gh:GokiProtocol/goki@0.5.2".parse::<Location>(),
+            Err(LocationError::InvalidGitHub(
+                "gh:GokiProtocol/goki@0.5.2".to_string()
+            ))
+        );
+        assert_eq!(
+            "chain:mainnet:not-an-address".parse::<Location>(),
+            Err(LocationError::InvalidAddress("not-an-address".to_string()))
+        );
+        assert_eq!(
+            "anchor:".parse::<Location>(),
+            Err(LocationError::MissingName("program", "anchor:smart_wallet"))
+        );
+    }
+
+    #[test]
+    fn splits_checksums() {
+        let checksum = "AB".repeat(32);
+        let raw = format!("anchor:smart_wallet#sha256={}", checksum);
+        assert_eq!(
+            split_checksum(&raw).unwrap(),
+            ("anchor:smart_wallet", Some(checksum.to_ascii_lowercase()))
+        );
+        assert_eq!(
+            split_checksum("anchor:smart_wallet#sha256=abc"),
+            Err(LocationError::InvalidChecksum("abc".to_string()))
+        );
+        assert_eq!(
+            split_checksum("anchor:smart_wallet").unwrap(),
+            ("anchor:smart_wallet", None)
+        );
+    }
+}
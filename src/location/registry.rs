@@ -0,0 +1,171 @@
+//! Reads artifacts from a Solana Program Registry index.
+
+use anyhow::{format_err, Result};
+use std::{
+    fs::{self, File},
+    io::{copy, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use super::{http::HttpClient, ArtifactMetadata};
+
+/// The public Solana Program Registry index.
+pub const DEFAULT_INDEX_URL: &str =
+    "https://raw.githubusercontent.com/DeployDAO/solana-program-index/master";
+
+/// Directory of the index containing one `<id>@<version>.json` file per artifact.
+const ARTIFACTS_BY_ID: &str = "artifacts-by-id";
+
+/// A Solana Program Registry index, either served over HTTP or stored in a local directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegistryIndex {
+    Remote { url: String },
+    Local { path: PathBuf },
+}
+
+fn is_remote(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+impl RegistryIndex {
+    /// Parses an index URL, which may be an HTTP(S) URL, a `file://` URL or a directory.
+    pub fn new(index_url: &str) -> Self {
+        if is_remote(index_url) {
+            RegistryIndex::Remote {
+                url: index_url.trim_end_matches('/').to_string(),
+            }
+        } else {
+            RegistryIndex::Local {
+                path: PathBuf::from(index_url.strip_prefix("file://").unwrap_or(index_url)),
+            }
+        }
+    }
+
+    /// URL or path of the metadata of an artifact.
+    pub fn artifact_url(&self, id: &str, version: &str) -> String {
+        let file_name = format!("{}/{}@{}.json", ARTIFACTS_BY_ID, id, version);
+        match self {
+            RegistryIndex::Remote { url } => format!("{}/{}", url, file_name),
+            RegistryIndex::Local { path } => path.join(file_name).display().to_string(),
+        }
+    }
+
+    /// Fetches the metadata of an artifact.
+    pub async fn get_artifact(
+        &self,
+        http: &HttpClient,
+        id: &str,
+        version: &str,
+    ) -> Result<ArtifactMetadata> {
+        let metadata_url = self.artifact_url(id, version);
+        match self {
+            RegistryIndex::Remote { .. } => {
+                let response = http.get(&metadata_url).await?;
+                if !response.status().is_success() {
+                    return Err(format_err!(
+                        "artifact {}@{} not found in registry ({})",
+                        id,
+                        version,
+                        response.status()
+                    ));
+                }
+                Ok(response.json().await?)
+            }
+            RegistryIndex::Local { path } => {
+                let metadata_path = PathBuf::from(metadata_url);
+                if !metadata_path.exists() {
+                    return Err(format_err!(
+                        "artifact {}@{} not found in registry at {}",
+                        id,
+                        version,
+                        path.display()
+                    ));
+                }
+                read_metadata(&metadata_path)
+            }
+        }
+    }
+
+    /// Lists all artifacts in the index.
+    ///
+    /// Only local indexes can be listed, since remote indexes do not publish a listing of
+    /// their artifacts and directories cannot be listed over HTTP.
+    pub fn list_artifacts(&self) -> Result<Vec<ArtifactMetadata>> {
+        let mut artifacts: Vec<ArtifactMetadata> = match self {
+            RegistryIndex::Remote { url } => {
+                return Err(format_err!(
+                    "the registry index at {} is remote and cannot be searched; clone the index and set `registry.index_url` to its path to search it",
+                    url
+                ))
+            }
+            RegistryIndex::Local { path } => {
+                let mut artifacts = vec![];
+                read_metadata_dir(&path.join(ARTIFACTS_BY_ID), &mut artifacts)?;
+                artifacts
+            }
+        };
+        artifacts.sort_by(|a, b| (&a.id, &a.tag).cmp(&(&b.id, &b.tag)));
+        artifacts.dedup_by(|a, b| a.id == b.id && a.tag == b.tag);
+        Ok(artifacts)
+    }
+
+    /// Copies an artifact binary stored alongside a local index into `program_file`.
+    /// Relative paths are resolved against the index; remote indexes may only have HTTP URLs.
+    ///
+    /// Returns `false` if the artifact URL is remote and must be downloaded instead.
+    pub fn copy_local_artifact(
+        &self,
+        artifact: &ArtifactMetadata,
+        program_file: &mut File,
+    ) -> Result<bool> {
+        if is_remote(&artifact.url) {
+            return Ok(false);
+        }
+        let artifact_path = Path::new(
+            artifact
+                .url
+                .strip_prefix("file://")
+                .unwrap_or(&artifact.url),
+        );
+        let artifact_path = match self {
+            RegistryIndex::Local { path } => path.join(artifact_path),
+            // A remote index must not make goki read arbitrary local files.
+            RegistryIndex::Remote { url } => {
+                return Err(format_err!(
+                    "artifact {} of remote index {} has the non-HTTP URL {}",
+                    artifact.id,
+                    url,
+                    artifact.url
+                ))
+            }
+        };
+        program_file.set_len(0)?;
+        program_file.seek(SeekFrom::Start(0))?;
+        copy(&mut File::open(&artifact_path)?, program_file)?;
+        Ok(true)
+    }
+}
+
+/// Reads all artifact metadata files, named `<id>@<version>.json`, in `dir`, including
+/// those in subdirectories, since artifact IDs contain slashes.
+fn read_metadata_dir(dir: &Path, artifacts: &mut Vec<ArtifactMetadata>) -> Result<()> {
+    for file in
+        fs::read_dir(dir).map_err(|e| format_err!("could not read {}: {}", dir.display(), e))?
+    {
+        let file_path = file?.path();
+        if file_path.is_dir() {
+            read_metadata_dir(&file_path, artifacts)?;
+        } else if file_path.extension().and_then(|ext| ext.to_str()) == Some("json")
+            && matches!(file_path.file_name().and_then(|name| name.to_str()), Some(name) if name.contains('@'))
+        {
+            artifacts.push(read_metadata(&file_path)?);
+        }
+    }
+    Ok(())
+}
+
+fn read_metadata(path: &Path) -> Result<ArtifactMetadata> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents)
+        .map_err(|e| format_err!("invalid artifact metadata {}: {}", path.display(), e))
+}
@@ -11,12 +11,12 @@ macro_rules! solana_cmd {
 
 #[macro_export]
 macro_rules! anchor_cmd {
-    ($workspace:expr, $cmd:expr) => {
+    ($workspace:expr, $cluster:expr, $cmd:expr) => {
         std::process::Command::new("anchor")
             .arg($cmd)
             .arg("--provider.cluster")
-            .arg(&$workspace.network.to_string())
+            .arg($workspace.get_cluster_url($cluster)?)
             .arg("--provider.wallet")
-            .arg(&$workspace.deployer_path)
+            .arg($workspace.get_deployer_kp_path_if_exists($cluster)?)
     };
 }
@@ -0,0 +1,182 @@
+//! Per-cluster policies configured in `[clusters.<name>]`.
+
+use anyhow::{format_err, Result};
+use colored::*;
+use solana_sdk::{
+    bpf_loader_upgradeable::UpgradeableLoaderState,
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+    signature::read_keypair_file,
+    signer::Signer,
+};
+use std::{
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use crate::{
+    cluster::ClusterRef,
+    utils::{confirm, exec_command_with_output},
+    workspace::Workspace,
+};
+
+/// Prefixes of signers that are not keypair files, such as `usb://ledger`.
+const SIGNER_URI_PREFIXES: [&str; 4] = ["usb://", "prompt:", "file:", "stdin"];
+
+/// Whether `signer` is a signer URI understood by the `solana` CLI rather than a path to a
+/// keypair file.
+pub fn is_signer_uri(signer: &str) -> bool {
+    signer == "ASK"
+        || SIGNER_URI_PREFIXES
+            .iter()
+            .any(|prefix| signer.starts_with(prefix))
+}
+
+/// The public key of a signer: a path to a keypair file, or a signer URI, which is resolved
+/// with `solana address`.
+pub fn signer_pubkey(signer: &str) -> Result<Pubkey> {
+    if !is_signer_uri(signer) {
+        return Ok(read_keypair_file(signer)
+            .map_err(|e| format_err!("could not read upgrade authority {}: {}", signer, e))?
+            .pubkey());
+    }
+    let output = exec_command_with_output(
+        Command::new("solana")
+            .arg("address")
+            .arg("--keypair")
+            .arg(signer)
+            .stdin(Stdio::inherit()),
+    )?;
+    Pubkey::from_str(output.trim())
+        .map_err(|_| format_err!("could not resolve upgrade authority {}", signer))
+}
+
+/// The upgrade authority of a cluster.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpgradeAuthority {
+    /// A keypair file or signer URI, such as `usb://ledger`, that can sign locally.
+    Keypair(String),
+    /// A public key, for authorities that sign elsewhere, such as a Smart Wallet.
+    Pubkey(Pubkey),
+}
+
+impl UpgradeAuthority {
+    /// Parses a public key, or otherwise a path to a keypair or a signer URI.
+    pub fn parse(raw: &str) -> Self {
+        match Pubkey::from_str(raw) {
+            Ok(pubkey) => UpgradeAuthority::Pubkey(pubkey),
+            Err(_) => UpgradeAuthority::Keypair(raw.to_string()),
+        }
+    }
+
+    pub fn pubkey(&self) -> Result<Pubkey> {
+        match self {
+            UpgradeAuthority::Keypair(signer) => signer_pubkey(signer),
+            UpgradeAuthority::Pubkey(pubkey) => Ok(*pubkey),
+        }
+    }
+}
+
+/// Accounts the deployer creates for a program binary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgramAccounts {
+    /// A buffer holding the binary, as written by `solana program write-buffer`.
+    Buffer,
+    /// A new program and its program data account, which `solana program deploy` sizes for
+    /// up to twice the length of the binary.
+    Program,
+}
+
+impl Workspace {
+    /// The upgrade authority configured for a cluster, falling back to `upgrade_authority_keypair`.
+    pub fn get_upgrade_authority(&self, cluster: &ClusterRef) -> Result<Option<UpgradeAuthority>> {
+        Ok(self
+            .get_cluster(cluster)?
+            .upgrade_authority
+            .or_else(|| self.cfg.upgrade_authority_keypair.clone())
+            .map(|raw| UpgradeAuthority::parse(&raw)))
+    }
+
+    /// Fails if the upgrade authority may not sign locally on a cluster.
+    pub fn check_local_signing(&self, cluster: &ClusterRef) -> Result<()> {
+        if !self.get_cluster(cluster)?.allow_local_signing {
+            return Err(format_err!(
+                "local signing by the upgrade authority is disabled on cluster {}; upload a buffer with `goki upload-program-buffer` and upgrade through the upgrade authority instead",
+                cluster
+            ));
+        }
+        Ok(())
+    }
+
+    /// Asks for confirmation before `action` if the cluster requires it.
+    pub fn confirm_action(&self, cluster: &ClusterRef, action: &str) -> Result<()> {
+        if !self.get_cluster(cluster)?.require_confirmation {
+            return Ok(());
+        }
+        let prompt = format!(
+            "{} on cluster {}. Continue? [y/N] ",
+            action,
+            cluster.to_string().bold()
+        );
+        if !confirm(&prompt)? {
+            return Err(format_err!("aborted"));
+        }
+        Ok(())
+    }
+
+    /// Lamports needed to make the accounts for a program binary of `program_len` bytes
+    /// rent-exempt. Transaction fees are not included.
+    pub fn program_rent(
+        &self,
+        cluster: &ClusterRef,
+        program_len: u64,
+        accounts: ProgramAccounts,
+    ) -> Result<u64> {
+        let program_len = program_len as usize;
+        let sizes = match accounts {
+            ProgramAccounts::Buffer => vec![UpgradeableLoaderState::buffer_len(program_len)?],
+            ProgramAccounts::Program => vec![
+                UpgradeableLoaderState::programdata_len(program_len * 2)?,
+                UpgradeableLoaderState::program_len()?,
+            ],
+        };
+        let rpc_client = self.rpc_client(cluster)?;
+        sizes.into_iter().try_fold(0, |total, size| {
+            let rent = rpc_client
+                .get_minimum_balance_for_rent_exemption(size)
+                .map_err(|e| format_err!("could not fetch the rent of {} bytes: {}", size, e))?;
+            Ok(total + rent)
+        })
+    }
+
+    /// Fails if the deployer would be left with less than the minimum balance of the cluster
+    /// after spending `spend` lamports.
+    pub fn check_deployer_balance(&self, cluster: &ClusterRef, spend: u64) -> Result<()> {
+        let min_balance = match self.get_cluster(cluster)?.min_deployer_balance {
+            Some(min_balance) => sol_to_lamports(min_balance),
+            None => return Ok(()),
+        };
+        let (deployer, _) = self.get_deployer_pubkey(cluster)?.ok_or_else(|| {
+            format_err!(
+                "deployer of cluster {} not found; you may need to run `goki init`",
+                cluster
+            )
+        })?;
+        let balance = self
+            .rpc_client(cluster)?
+            .get_balance_with_commitment(&deployer, self.commitment(cluster)?)
+            .map_err(|e| format_err!("could not fetch balance of deployer {}: {}", deployer, e))?
+            .value;
+        if balance.saturating_sub(spend) < min_balance {
+            return Err(format_err!(
+                "deployer {} has {} SOL and needs {} SOL, but cluster {} requires it to keep at least {} SOL",
+                deployer,
+                lamports_to_sol(balance),
+                lamports_to_sol(spend),
+                cluster,
+                lamports_to_sol(min_balance)
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! Programs declared in `[programs.<name>]` of `Goki.toml`.
+
+use anyhow::{format_err, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+use crate::{cluster::ClusterRef, config::ProgramConfig, workspace::Workspace};
+
+/// Placeholder in program locations that is replaced with `--version`.
+pub const VERSION_PLACEHOLDER: &str = "{version}";
+
+/// Arguments that select a program declared in `Goki.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, clap::Args)]
+pub struct ProgramArgs {
+    /// Name of a program in `[programs]` of `Goki.toml`.
+    ///
+    /// Its program ID, location and expected upgrade authority are used unless given explicitly.
+    pub program: Option<String>,
+
+    /// Version of the program, substituted for `{version}` in its location.
+    #[clap(long)]
+    #[clap(requires = "program")]
+    pub version: Option<String>,
+}
+
+/// A program, resolved for a cluster from the command line and the manifest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResolvedProgram {
+    /// Name of the program in the manifest, if any.
+    pub name: Option<String>,
+    program_id: Option<String>,
+    location: Option<String>,
+    /// The upgrade authority the program is expected to have.
+    pub upgrade_authority: Option<Pubkey>,
+}
+
+impl ResolvedProgram {
+    fn missing(&self, what: &str, flag: &str) -> anyhow::Error {
+        match &self.name {
+            Some(name) => format_err!(
+                "no {} for program {}; pass {} or set `{}` in [programs.{}] of Goki.toml",
+                what,
+                name,
+                flag,
+                what.replace(' ', "_"),
+                name
+            ),
+            None => format_err!("no {} given; pass {} or the name of a program", what, flag),
+        }
+    }
+
+    /// The program ID.
+    pub fn program_id(&self) -> Result<&str> {
+        self.program_id
+            .as_deref()
+            .ok_or_else(|| self.missing("program id", "--program-id"))
+    }
+
+    /// The program ID, if known.
+    pub fn known_program_id(&self) -> Result<Option<Pubkey>> {
+        self.program_id
+            .as_ref()
+            .map(|program_id| {
+                Pubkey::from_str(program_id)
+                    .map_err(|_| format_err!("invalid program ID: {}", program_id))
+            })
+            .transpose()
+    }
+
+    /// The location of the program binary.
+    pub fn location(&self) -> Result<&str> {
+        self.location
+            .as_deref()
+            .ok_or_else(|| self.missing("location", "--location"))
+    }
+
+    /// Fails if `authority` is not the expected upgrade authority of the program.
+    pub fn check_upgrade_authority(&self, authority: &Pubkey) -> Result<()> {
+        match self.upgrade_authority {
+            Some(expected) if expected != *authority => Err(format_err!(
+                "upgrade authority {} does not match the expected upgrade authority {} of program {}",
+                authority,
+                expected,
+                self.name.as_deref().unwrap_or_default()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Substitutes `version` into a location template.
+pub fn render_location(template: &str, version: Option<&str>) -> Result<String> {
+    match version {
+        Some(version) if template.contains(VERSION_PLACEHOLDER) => {
+            Ok(template.replace(VERSION_PLACEHOLDER, version))
+        }
+        Some(_) => Err(format_err!(
+            "--version was given, but location {} does not contain {}",
+            template,
+            VERSION_PLACEHOLDER
+        )),
+        None if template.contains(VERSION_PLACEHOLDER) => Err(format_err!(
+            "location {} contains {}; pass --version",
+            template,
+            VERSION_PLACEHOLDER
+        )),
+        None => Ok(template.to_string()),
+    }
+}
+
+fn parse_pubkey(raw: Option<&String>, what: &str, name: &str) -> Result<Option<Pubkey>> {
+    raw.map(|raw| {
+        Pubkey::from_str(raw)
+            .map_err(|_| format_err!("invalid {} of program {}: {}", what, name, raw))
+    })
+    .transpose()
+}
+
+impl Workspace {
+    /// A program declared in `[programs.<name>]`.
+    pub fn get_program(&self, name: &str) -> Result<&ProgramConfig> {
+        self.cfg.programs.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.cfg.programs.keys().map(|k| k.as_str()).collect();
+            if known.is_empty() {
+                format_err!(
+                    "program {} not found; declare it in [programs.{}] of Goki.toml",
+                    name,
+                    name
+                )
+            } else {
+                format_err!(
+                    "program {} not found in Goki.toml; known programs: {}",
+                    name,
+                    known.join(", ")
+                )
+            }
+        })
+    }
+
+    /// Resolves the program selected by `args` on a cluster.
+    ///
+    /// `program_id` and `location`, if given, take precedence over the manifest.
+    pub fn resolve_program(
+        &self,
+        cluster: &ClusterRef,
+        args: &ProgramArgs,
+        program_id: Option<String>,
+        location: Option<String>,
+    ) -> Result<ResolvedProgram> {
+        let name = match &args.program {
+            Some(name) => name,
+            None => {
+                return Ok(ResolvedProgram {
+                    name: None,
+                    program_id,
+                    location,
+                    upgrade_authority: None,
+                })
+            }
+        };
+        let program = self.get_program(name)?;
+        let program_cluster = program.clusters.get(&cluster.to_string());
+
+        if location.is_some() && args.version.is_some() {
+            return Err(format_err!("--version cannot be used with --location"));
+        }
+        let location = match (location, &program.location) {
+            (Some(location), _) => Some(location),
+            (None, Some(template)) => Some(render_location(template, args.version.as_deref())?),
+            (None, None) => None,
+        };
+        let program_id = program_id.or_else(|| {
+            program_cluster
+                .and_then(|c| c.program_id.clone())
+                .or_else(|| program.program_id.clone())
+        });
+        let upgrade_authority = parse_pubkey(
+            program_cluster
+                .and_then(|c| c.upgrade_authority.as_ref())
+                .or(program.upgrade_authority.as_ref()),
+            "upgrade authority",
+            name,
+        )?;
+        Ok(ResolvedProgram {
+            name: Some(name.clone()),
+            program_id,
+            location,
+            upgrade_authority,
+        })
+    }
+}
@@ -1,6 +1,5 @@
 //! Calls to the `solana` CLI.
 
-use anchor_client::Cluster;
 use anyhow::Result;
 use solana_sdk::pubkey::Pubkey;
 use std::{
@@ -8,13 +7,24 @@ use std::{
     process::{Command, Output},
 };
 
-use crate::{utils::exec_command, workspace::Workspace};
+use crate::{cluster::ClusterRef, utils::exec_command_tee, workspace::Workspace};
 
 impl Workspace {
+    /// Executes a `solana` command, printing explorer links for the transactions it sent.
+    pub fn exec_solana_command(
+        &self,
+        cluster: &ClusterRef,
+        command: &mut Command,
+    ) -> Result<Output> {
+        let output = exec_command_tee(command)?;
+        self.print_explorer_links(cluster, &output);
+        Ok(output)
+    }
+
     /// Sets the buffer authority of a buffer.
     pub fn set_buffer_authority(
         &self,
-        cluster: &Cluster,
+        cluster: &ClusterRef,
         buffer_key: &Pubkey,
         authority: &str,
     ) -> Result<Output> {
@@ -31,15 +41,15 @@ impl Workspace {
     /// Sets the upgrade authority of a program.
     pub fn set_upgrade_authority(
         &self,
-        cluster: &Cluster,
+        cluster: &ClusterRef,
         program_id: &Pubkey,
         current_authority: &Path,
         new_authority: &str,
     ) -> Result<Output> {
-        exec_command(
+        self.exec_solana_command(
+            cluster,
             std::process::Command::new("solana")
-                .arg("--url")
-                .arg(self.get_cluster_url(cluster)?)
+                .args(self.cluster_args(cluster)?)
                 .arg("--keypair")
                 .arg(current_authority)
                 .arg("program")
@@ -53,7 +63,7 @@ impl Workspace {
     /// Writes a program buffer.
     pub fn write_buffer(
         &self,
-        cluster: &Cluster,
+        cluster: &ClusterRef,
         program_file: &Path,
         buffer_kp_file: &Path,
     ) -> Result<Output> {
@@ -70,7 +80,7 @@ impl Workspace {
     /// Deploys a program.
     pub fn deploy(
         &self,
-        cluster: &Cluster,
+        cluster: &ClusterRef,
         program_file: &Path,
         program_kp_path: &Path,
     ) -> Result<Output> {
@@ -87,15 +97,15 @@ impl Workspace {
     /// Upgrades a program.
     pub fn upgrade(
         &self,
-        cluster: &Cluster,
+        cluster: &ClusterRef,
         upgrade_authority_kp: &str,
         buffer_key: &Pubkey,
         program_id: &str,
     ) -> Result<Output> {
-        exec_command(
+        self.exec_solana_command(
+            cluster,
             std::process::Command::new("solana")
-                .arg("--url")
-                .arg(self.get_cluster_url(cluster)?)
+                .args(self.cluster_args(cluster)?)
                 .arg("--keypair")
                 .arg(upgrade_authority_kp)
                 .arg("program")
@@ -1,17 +1,17 @@
-use anchor_client::Cluster;
 use anyhow::{format_err, Result};
 use std::{thread, time::Duration};
 
+use crate::cluster::ClusterRef;
 use crate::workspace::Workspace;
 
 pub fn process(
     workspace: &Workspace,
-    cluster: &Cluster,
+    cluster: &ClusterRef,
     amount: &str,
     iterations: u32,
     interval: u64,
 ) -> Result<()> {
-    if *cluster == Cluster::Mainnet {
+    if cluster.is_mainnet() {
         return Err(format_err!("cannot request an airdrop from mainnet"));
     }
 
@@ -1,9 +1,9 @@
-use anchor_client::Cluster;
 use anyhow::Result;
 
+use crate::cluster::ClusterRef;
 use crate::workspace::Workspace;
 
-pub fn process(workspace: &Workspace, cluster: &Cluster) -> Result<()> {
+pub fn process(workspace: &Workspace, cluster: &ClusterRef) -> Result<()> {
     let ctx = workspace.new_cluster_context(cluster)?;
     let deployer = ctx.parse_wallet_alias("deployer")?;
 
@@ -0,0 +1,45 @@
+use anyhow::Result;
+use colored::*;
+use std::time::Duration;
+
+use crate::{cli::CacheCommand, workspace::Workspace};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub fn process(workspace: &Workspace, command: CacheCommand) -> Result<()> {
+    let cache = workspace.artifact_cache();
+    match command {
+        CacheCommand::Ls => {
+            let mut entries = cache.entries()?;
+            if entries.is_empty() {
+                println!("Cache at {} is empty.", cache.dir().display());
+                return Ok(());
+            }
+            entries.sort_by_key(|entry| entry.fetched_at);
+            for entry in entries.iter() {
+                println!("{}", entry.location.bold());
+                println!("  SHA256: {}", entry.sha256.green());
+                println!("  Size (bytes): {}", entry.size.to_string().green());
+                println!(
+                    "  Fetched: {} days ago",
+                    (entry.age().as_secs() / SECONDS_PER_DAY)
+                        .to_string()
+                        .green()
+                );
+            }
+        }
+        CacheCommand::Prune { older_than } => {
+            let max_age = older_than.map(|days| Duration::from_secs(days * SECONDS_PER_DAY));
+            let removed = cache.prune(max_age)?;
+            for path in removed.iter() {
+                println!("Removed {}", path.display());
+            }
+            println!("Pruned {} cached programs.", removed.len());
+        }
+        CacheCommand::Clear => {
+            cache.clear()?;
+            println!("Cleared cache at {}.", cache.dir().display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use anyhow::{format_err, Result};
+use colored::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use toml::Value;
+
+use crate::{
+    cli::ConfigCommand,
+    config::{
+        edit, find_workspace_config, format_key, global_config_path,
+        interpolate::redact,
+        migrate, parse_key,
+        validate::{self, Diagnostic, Problem},
+        ConfigLayers, Origin, CONFIG_FILE_NAME,
+    },
+    workspace::{config_start_dir, Workspace},
+};
+
+/// Formats a value for printing, redacting secrets. Strings are printed without quotes.
+fn fmt_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => redact(s),
+        value => redact(&value.to_string()),
+    }
+}
+
+fn print_value(layers: &ConfigLayers, key: &[String], value: &Value, show_origin: bool) {
+    let origin = match layers.origin(key) {
+        Some(origin) if show_origin => format!(" ({})", origin).dimmed().to_string(),
+        _ => String::new(),
+    };
+    println!(
+        "{} = {}{}",
+        format_key(key).bold(),
+        redact(&value.to_string()),
+        origin
+    );
+}
+
+/// The config file to edit: the global config, or the nearest `Goki.toml`.
+fn config_file_path(workspace_config_path: Option<PathBuf>, global: bool) -> Result<PathBuf> {
+    if global {
+        global_config_path().ok_or_else(|| format_err!("could not find the home directory"))
+    } else {
+        workspace_config_path
+            .ok_or_else(|| format_err!("{} not found; please run `goki init`", CONFIG_FILE_NAME))
+    }
+}
+
+/// Migrates a config file to the latest format.
+fn migrate(workspace_path: Option<&Path>, global: bool, dry_run: bool) -> Result<()> {
+    // The config may not load until it is migrated, so only look for the file.
+    let path = config_file_path(
+        find_workspace_config(&config_start_dir(workspace_path)?),
+        global,
+    )?;
+    let mut doc = edit::read_document(&path)?;
+    let from = migrate::version(&doc)?;
+    if from == migrate::CURRENT_VERSION {
+        println!(
+            "{} is already at the latest config version, {}",
+            path.display(),
+            from
+        );
+        return Ok(());
+    }
+    let changes = migrate::migrate(&mut doc)?;
+    println!(
+        "Migrating {} from config version {} to {}",
+        path.display(),
+        from,
+        migrate::CURRENT_VERSION
+    );
+    for change in changes.iter() {
+        println!("  {}", change);
+    }
+    if dry_run {
+        println!("{}", doc);
+    } else {
+        edit::write_document(&path, &doc)?;
+        println!("{}", "Migration complete.".green());
+    }
+    Ok(())
+}
+
+/// Locates a problem in the merged config by the layer its value came from.
+fn locate(layers: &ConfigLayers, problem: Problem) -> Diagnostic {
+    // Missing values are located at their table.
+    let origin = layers.origin(&problem.key).or_else(|| {
+        let table = &problem.key[..problem.key.len() - 1];
+        layers
+            .entries()
+            .find(|(path, _, _)| path.starts_with(table))
+            .map(|(_, _, origin)| origin)
+    });
+    let location = match origin {
+        Some(Origin::Global(path) | Origin::Workspace(path)) => {
+            let line_col = fs::read_to_string(path)
+                .ok()
+                .and_then(|source| validate::locate(&source, &problem.key));
+            match line_col {
+                Some((line, col)) => format!("{}:{}:{}", path.display(), line, col),
+                None => path.display().to_string(),
+            }
+        }
+        Some(origin) => origin.to_string(),
+        None => "config".to_string(),
+    };
+    Diagnostic {
+        location,
+        message: format!("`{}`: {}", format_key(&problem.key), problem.message),
+    }
+}
+
+/// Reports all problems in the config.
+fn check(workspace_path: Option<&Path>, overrides: &[String]) -> Result<()> {
+    let (layers, mut diagnostics) = Workspace::check_config(workspace_path, overrides)?;
+    // Problems in the merged config are only meaningful if every layer is valid.
+    if diagnostics.is_empty() {
+        match layers.config() {
+            Ok(cfg) => diagnostics.extend(
+                validate::check_config(&cfg)
+                    .into_iter()
+                    .map(|problem| locate(&layers, problem)),
+            ),
+            Err(err) => diagnostics.push(Diagnostic {
+                location: "config".to_string(),
+                message: err.to_string(),
+            }),
+        }
+    }
+    if diagnostics.is_empty() {
+        println!("{}", "Config OK".green());
+        return Ok(());
+    }
+    for diagnostic in diagnostics.iter() {
+        println!("{}: {}", "error".red().bold(), diagnostic);
+    }
+    Err(format_err!(
+        "found {} problem{} in the config",
+        diagnostics.len(),
+        if diagnostics.len() == 1 { "" } else { "s" }
+    ))
+}
+
+pub fn process(
+    workspace_path: Option<&Path>,
+    overrides: &[String],
+    command: ConfigCommand,
+) -> Result<()> {
+    match command {
+        ConfigCommand::Check => return check(workspace_path, overrides),
+        ConfigCommand::Migrate { global, dry_run } => {
+            return migrate(workspace_path, global, dry_run)
+        }
+        _ => {}
+    }
+    let layers = Workspace::load_config(workspace_path, overrides)?;
+    match command {
+        ConfigCommand::Get { key, show_origin } => {
+            let key = parse_key(&key)?;
+            let value = layers
+                .get(&key)
+                .ok_or_else(|| format_err!("config value {} is not set", format_key(&key)))?;
+            if show_origin {
+                match value {
+                    Value::Table(_) => {
+                        for (path, value, _) in layers
+                            .entries()
+                            .filter(|(path, _, _)| path.starts_with(&key))
+                        {
+                            print_value(&layers, path, value, true);
+                        }
+                    }
+                    value => print_value(&layers, &key, value, true),
+                }
+            } else {
+                println!("{}", fmt_value(value));
+            }
+        }
+        ConfigCommand::Set { key, value, global } => {
+            let key = parse_key(&key)?;
+            let path = config_file_path(layers.workspace_config_path.clone(), global)?;
+            let mut doc = edit::read_document(&path)?;
+            edit::set_value(&mut doc, &key, &value)?;
+            edit::write_document(&path, &doc)?;
+            println!("Set {} in {}", format_key(&key).bold(), path.display());
+        }
+        ConfigCommand::List { show_origin } => {
+            for (path, value, _) in layers.entries() {
+                print_value(&layers, path, value, show_origin);
+            }
+        }
+        ConfigCommand::Check | ConfigCommand::Migrate { .. } => unreachable!(),
+    }
+    Ok(())
+}
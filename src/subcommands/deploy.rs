@@ -1,4 +1,3 @@
-use anchor_client::Cluster;
 use anyhow::{format_err, Result};
 use colored::*;
 use solana_sdk::pubkey::Pubkey;
@@ -9,36 +8,64 @@ use std::path::Path;
 use std::str::FromStr;
 use tempfile::NamedTempFile;
 
+use crate::cluster::ClusterRef;
+use crate::policy::ProgramAccounts;
+use crate::program::ResolvedProgram;
 use crate::utils::sha256_digest;
-use crate::{location::fetch_program_file, workspace::Workspace};
+use crate::{location::FetchOptions, workspace::Workspace};
 
 pub async fn process(
     workspace: &Workspace,
-    cluster: Cluster,
+    cluster: ClusterRef,
     upgrade_authority_provided: Option<String>,
-    location_or_buffer: String,
+    program: &ResolvedProgram,
     program_kp_path: &Path,
+    fetch_options: &FetchOptions,
 ) -> Result<()> {
+    let location_or_buffer = program.location()?;
     let deployer_kp_path = workspace.get_deployer_kp_path_if_exists(&cluster)?;
     let program_kp = solana_sdk::signature::read_keypair_file(program_kp_path)
         .map_err(|e| format_err!("could not open program kp path: {}", e))?;
+    if let Some(program_id) = program.known_program_id()? {
+        if program_id != program_kp.pubkey() {
+            return Err(format_err!(
+                "program keypair {} does not match the program ID {} in Goki.toml",
+                program_kp.pubkey(),
+                program_id
+            ));
+        }
+    }
 
+    let upgrade_authority_provided = match upgrade_authority_provided {
+        Some(pubkey_str) => Some(Pubkey::from_str(&pubkey_str)?),
+        None => program.upgrade_authority,
+    };
     let upgrade_authority: Pubkey = match upgrade_authority_provided {
-        Some(pubkey_str) => Pubkey::from_str(&pubkey_str)?,
-        None => {
-            if cluster == Cluster::Mainnet {
-                return Err(format_err!(
-                    "Must specify the --upgrade authority public key on mainnet."
-                ));
+        Some(pubkey) => pubkey,
+        None => match workspace.get_upgrade_authority(&cluster)? {
+            Some(upgrade_authority) => upgrade_authority.pubkey()?,
+            None => {
+                if cluster.is_mainnet() {
+                    return Err(format_err!(
+                        "Must specify the --upgrade authority public key on mainnet."
+                    ));
+                }
+                let deployer_kp = solana_sdk::signature::read_keypair_file(&deployer_kp_path)
+                    .map_err(|_| format_err!("could not open deployer KP"))?;
+                deployer_kp.try_pubkey()?
             }
-            let deployer_kp = solana_sdk::signature::read_keypair_file(&deployer_kp_path)
-                .map_err(|_| format_err!("could not open deployer KP"))?;
-            deployer_kp.try_pubkey()?
-        }
+        },
     };
+    program.check_upgrade_authority(&upgrade_authority)?;
 
     let mut program_file = NamedTempFile::new()?;
-    fetch_program_file(&mut program_file, location_or_buffer.as_str()).await?;
+    workspace
+        .fetch_program_file(
+            program_file.as_file_mut(),
+            location_or_buffer,
+            fetch_options,
+        )
+        .await?;
 
     let input = File::open(program_file.path())?;
     let mut reader = BufReader::new(input);
@@ -47,6 +74,18 @@ pub async fn process(
     println!("Size (bytes): {}", program_file_size.to_string().green());
     println!("SHA256: {}", program_file_digest.green());
 
+    workspace.check_deployer_balance(
+        &cluster,
+        workspace.program_rent(&cluster, program_file_size, ProgramAccounts::Program)?,
+    )?;
+    workspace.confirm_action(
+        &cluster,
+        &format!(
+            "Deploying program {} with upgrade authority {}",
+            program_kp.pubkey(),
+            upgrade_authority
+        ),
+    )?;
     workspace.deploy(&cluster, program_file.path(), program_kp_path)?;
     workspace.set_upgrade_authority(
         &cluster,
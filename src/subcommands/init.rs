@@ -4,66 +4,166 @@ use colored::*;
 use solana_sdk::{pubkey::Pubkey, signature::read_keypair_file, signer::Signer};
 use std::fs::File;
 use std::io::Write;
+use std::path::PathBuf;
 use std::{fs, path::Path};
 
-use crate::utils::{exec_command, gen_keypair_file};
-use crate::{config::Config, workspace::Workspace};
+use crate::cluster::ClusterRef;
+use crate::config::{find_workspace_config, Config, CONFIG_FILE_NAME};
+use crate::keystore::{keystore_path, write_private_keypair, Keystore};
+use crate::utils::gen_keypair_file;
+use crate::workspace::{config_start_dir, new_config_dir, Workspace};
 
-pub fn process(path: &Path) -> Result<()> {
-    let maybe_cfg = Config::discover()?;
-    let cfg = if let Some(cfg) = maybe_cfg {
-        println!("Goki.toml already exists in workspace");
-        cfg.into_inner()
-    } else {
-        let cfg = Config::default();
-        let mut file = File::create("Goki.toml")?;
-        file.write_all(cfg.to_string().as_bytes())?;
-        cfg
-    };
+/// Finds `Goki.toml`, creating it if it does not exist.
+///
+/// New config files are created next to the workspace directory if one is given,
+/// and in the current directory otherwise.
+fn ensure_config(path: Option<&Path>) -> Result<()> {
+    let start_dir = config_start_dir(path)?;
+    if let Some(cfg_path) = find_workspace_config(&start_dir) {
+        println!("{} already exists", cfg_path.display());
+        return Ok(());
+    }
+    let cfg_dir = new_config_dir(path)?;
+    fs::create_dir_all(&cfg_dir)?;
+    let cfg_path = cfg_dir.join(CONFIG_FILE_NAME);
+    let mut file = File::create(&cfg_path)?;
+    file.write_all(Config::template().as_bytes())?;
+    println!("Created {}", cfg_path.display());
+    Ok(())
+}
 
-    let workspace = &Workspace {
-        path: path.to_path_buf(),
-        cfg,
-    };
+/// Finds the deployer keypair of a cluster in an existing workspace directory,
+/// and whether it is encrypted.
+fn find_existing_deployer(
+    existing_dir: &Path,
+    cluster: &ClusterRef,
+) -> Result<Option<(PathBuf, bool)>> {
+    let keypair_path = existing_dir
+        .join("deployers")
+        .join(format!("{}.json", cluster));
+    if keypair_path.exists() {
+        read_keypair_file(&keypair_path)
+            .map_err(|_| format_err!("could not read keypair {}", keypair_path.display()))?;
+        return Ok(Some((keypair_path, false)));
+    }
+    let encrypted_kp_path = keystore_path(&keypair_path);
+    if encrypted_kp_path.exists() {
+        Keystore::read(&encrypted_kp_path)?.pubkey()?;
+        return Ok(Some((encrypted_kp_path, true)));
+    }
+    Ok(None)
+}
+
+/// Requests an airdrop for the deployer, printing a warning instead of failing.
+fn try_airdrop(workspace: &Workspace, cluster: &ClusterRef) {
+    let result = workspace.cluster_args(cluster).and_then(|cluster_args| {
+        workspace.exec_solana_command(
+            cluster,
+            std::process::Command::new("solana")
+                .args(cluster_args)
+                .arg("--keypair")
+                .arg(workspace.get_deployer_kp_path(cluster))
+                .arg("airdrop")
+                .arg("1"),
+        )
+    });
+    if let Err(err) = result {
+        eprintln!(
+            "{} could not airdrop to the deployer on {}: {}. Run `goki airdrop -c {}` to try again.",
+            "Warning:".yellow(),
+            cluster,
+            err,
+            cluster
+        );
+    }
+}
+
+/// Initializes a workspace. Anything that already exists is kept, so this may be re-run
+/// to fill in what is missing.
+pub fn process(
+    path: Option<&Path>,
+    overrides: &[String],
+    clusters: Vec<ClusterRef>,
+    no_airdrop: bool,
+    from_existing: Option<&Path>,
+    config_only: bool,
+) -> Result<()> {
+    if let Some(existing_dir) = from_existing {
+        if !existing_dir.join("deployers").is_dir() {
+            return Err(format_err!(
+                "{} is not a Goki workspace directory: it has no deployers/ directory",
+                existing_dir.display()
+            ));
+        }
+    }
+    ensure_config(path)?;
+    if config_only {
+        return Ok(());
+    }
+
+    let workspace = &Workspace::load(path, overrides)?;
 
     fs::create_dir_all(workspace.deployer_dir())?;
 
-    let mut result: Vec<(Cluster, Pubkey)> = vec![];
+    let clusters = if clusters.is_empty() {
+        workspace.clusters()?
+    } else {
+        clusters
+    };
+    // Deployers, and whether they were newly generated.
+    let mut result: Vec<(ClusterRef, Pubkey, bool)> = vec![];
 
-    for cluster in [Cluster::Devnet, Cluster::Testnet, Cluster::Mainnet].iter() {
+    for cluster in clusters.iter() {
         let keypair_path = workspace.get_deployer_kp_path(cluster);
-        let key = if keypair_path.exists() {
-            let kp = read_keypair_file(keypair_path)
-                .map_err(|_| format_err!("could not read keypair"))?;
-            let pubkey = kp.pubkey();
+        let existing = match from_existing {
+            Some(existing_dir) => find_existing_deployer(existing_dir, cluster)?,
+            None => None,
+        };
+        let mut generated = false;
+        let key = if let Some((pubkey, _)) = workspace.get_deployer_pubkey(cluster)? {
             println!("Keypair at {} already exists: {}", cluster, pubkey);
             pubkey
+        } else if let Some((existing_path, encrypted)) = existing {
+            let pubkey = if encrypted {
+                let keystore = Keystore::read(&existing_path)?;
+                keystore.write(&keystore_path(&keypair_path))?;
+                keystore.pubkey()?
+            } else {
+                let keypair = read_keypair_file(&existing_path)
+                    .map_err(|_| format_err!("could not read keypair"))?;
+                write_private_keypair(&keypair_path, &keypair)?;
+                keypair.pubkey()
+            };
+            println!(
+                "Copied keypair at {} from {}: {}",
+                cluster,
+                existing_path.display(),
+                pubkey
+            );
+            pubkey
         } else {
+            generated = true;
             gen_keypair_file(&keypair_path)?
         };
-        result.push((cluster.clone(), key));
+        result.push((cluster.clone(), key, generated));
     }
 
     println!("{}", "Deployers:".bold());
-    for (cluster, key) in result.iter() {
+    for (cluster, key, _) in result.iter() {
         println!("{}: {}", cluster, key);
     }
 
-    let workspace = workspace.reload()?;
-
-    for (cluster, _key) in result.iter() {
-        if cluster.clone() != Cluster::Mainnet {
-            let path_string = format!(".goki/deployers/{}.json", cluster);
-            let keypair_path = Path::new(path_string.as_str());
-            exec_command(
-                std::process::Command::new("solana")
-                    .arg("--url")
-                    .arg(workspace.get_cluster_url(cluster)?)
-                    .arg("--keypair")
-                    .arg(keypair_path)
-                    .arg("airdrop")
-                    .arg("1"),
-            )?;
+    if !no_airdrop {
+        let workspace = workspace.reload()?;
+        for (cluster, _key, generated) in result.iter() {
+            if *generated
+                && matches!(
+                    cluster,
+                    ClusterRef::Builtin(Cluster::Devnet | Cluster::Testnet)
+                )
+            {
+                try_airdrop(&workspace, cluster);
+            }
         }
     }
 
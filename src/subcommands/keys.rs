@@ -0,0 +1,472 @@
+use anyhow::{format_err, Result};
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use colored::*;
+use indicatif::{ProgressBar, ProgressStyle};
+use solana_sdk::{
+    derivation_path::DerivationPath,
+    native_token::lamports_to_sol,
+    signature::{keypair_from_seed_and_derivation_path, read_keypair_file, Keypair},
+    signer::Signer,
+};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    cli::KeysCommand,
+    cluster::ClusterRef,
+    keystore::{
+        read_new_password, read_password, write_private_keypair, Keystore, NEW_PASSWORD_ENV,
+        PASSWORD_ENV,
+    },
+    workspace::Workspace,
+};
+
+/// Derivation path of mnemonic keypairs if none is given, the same as most Solana wallets.
+const DEFAULT_DERIVATION_PATH: &str = "m/44'/501'/0'/0'";
+
+/// Prefix of every Solana derivation path: BIP44, coin type 501.
+const SOLANA_DERIVATION_PREFIX: &str = "m/44'/501'";
+
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Vanity prefixes longer than this may take hours or more to find.
+const LONG_VANITY_PREFIX: usize = 5;
+
+/// Parses a derivation path of the form `m/44'/501'/<account>'/<change>'`.
+fn parse_derivation_path(path: &str) -> Result<DerivationPath> {
+    let invalid = || {
+        format_err!(
+            "invalid derivation path `{}`: expected {}/<account>'/<change>'",
+            path,
+            SOLANA_DERIVATION_PREFIX
+        )
+    };
+    let rest = path
+        .strip_prefix(SOLANA_DERIVATION_PREFIX)
+        .ok_or_else(invalid)?;
+    if rest.is_empty() {
+        return Ok(DerivationPath::new_bip44(None, None));
+    }
+    let rest = rest.strip_prefix('/').ok_or_else(invalid)?;
+    DerivationPath::from_key_str(rest).map_err(|e| format_err!("{}: {}", invalid(), e))
+}
+
+/// Derives a keypair from a new mnemonic, printing the phrase for backup.
+fn new_mnemonic_keypair(word_count: usize, derivation_path: &str) -> Result<Keypair> {
+    let mnemonic_type = MnemonicType::for_word_count(word_count).map_err(|_| {
+        format_err!(
+            "invalid word count {}: expected 12, 15, 18, 21 or 24",
+            word_count
+        )
+    })?;
+    let derivation_path_parsed = parse_derivation_path(derivation_path)?;
+    let mnemonic = Mnemonic::new(mnemonic_type, Language::English);
+    let seed = Seed::new(&mnemonic, "");
+    let keypair =
+        keypair_from_seed_and_derivation_path(seed.as_bytes(), Some(derivation_path_parsed))
+            .map_err(|e| format_err!("could not derive keypair: {}", e))?;
+
+    println!("{}", "Save this seed phrase to recover the keypair:".bold());
+    println!("{}", mnemonic.phrase().yellow());
+    println!("Derivation path: {}", derivation_path);
+    println!("It will not be shown again.");
+    Ok(keypair)
+}
+
+/// Whether `pubkey` starts with `prefix`.
+fn matches_prefix(pubkey: &str, prefix: &str, ignore_case: bool) -> bool {
+    if ignore_case {
+        pubkey
+            .get(..prefix.len())
+            .map(|start| start.eq_ignore_ascii_case(prefix))
+            .unwrap_or(false)
+    } else {
+        pubkey.starts_with(prefix)
+    }
+}
+
+/// Generates keypairs on `threads` threads until one has a public key starting with `prefix`.
+fn grind_vanity_keypair(prefix: &str, ignore_case: bool, threads: usize) -> Result<Keypair> {
+    if let Some(c) = prefix.chars().find(|c| !BASE58_ALPHABET.contains(*c)) {
+        return Err(format_err!(
+            "invalid vanity prefix `{}`: `{}` is not a base58 character",
+            prefix,
+            c
+        ));
+    }
+    if prefix.len() > LONG_VANITY_PREFIX {
+        eprintln!(
+            "{} a {} character prefix may take a very long time to find",
+            "Warning:".yellow(),
+            prefix.len()
+        );
+    }
+    println!(
+        "Searching for a public key starting with {} on {} threads",
+        prefix.bold(),
+        threads
+    );
+
+    let found = Arc::new(AtomicBool::new(false));
+    let attempts = Arc::new(AtomicU64::new(0));
+    let (sender, receiver) = mpsc::channel();
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let found = found.clone();
+            let attempts = attempts.clone();
+            let sender = sender.clone();
+            let prefix = prefix.to_string();
+            thread::spawn(move || {
+                while !found.load(Ordering::Relaxed) {
+                    let keypair = Keypair::new();
+                    attempts.fetch_add(1, Ordering::Relaxed);
+                    if matches_prefix(&keypair.pubkey().to_string(), &prefix, ignore_case)
+                        && !found.swap(true, Ordering::Relaxed)
+                    {
+                        let _ = sender.send(keypair);
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let progress = ProgressBar::new_spinner();
+    progress.set_style(
+        ProgressStyle::default_spinner().template("{spinner:.green} [{elapsed_precise}] {msg}"),
+    );
+    let keypair = loop {
+        match receiver.recv_timeout(Duration::from_millis(200)) {
+            Ok(keypair) => break keypair,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                progress.set_message(format!(
+                    "{} keypairs tried",
+                    attempts.load(Ordering::Relaxed)
+                ));
+                progress.tick();
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(format_err!("vanity search stopped unexpectedly"));
+            }
+        }
+    };
+    for handle in handles {
+        let _ = handle.join();
+    }
+    progress.finish_with_message(format!(
+        "Found after {} keypairs",
+        attempts.load(Ordering::Relaxed)
+    ));
+    Ok(keypair)
+}
+
+pub fn process(workspace: &Workspace, command: KeysCommand) -> Result<()> {
+    match command {
+        KeysCommand::New {
+            cluster,
+            mnemonic,
+            word_count,
+            derivation_path,
+            vanity,
+            ignore_case,
+            threads,
+            force,
+        } => {
+            new_deployer(
+                workspace,
+                &cluster,
+                mnemonic,
+                word_count,
+                derivation_path,
+                vanity,
+                ignore_case,
+                threads,
+                force,
+            )?;
+        }
+        KeysCommand::Encrypt { cluster } => encrypt(workspace, &cluster)?,
+        KeysCommand::Decrypt { cluster } => decrypt(workspace, &cluster)?,
+        KeysCommand::Rotate { cluster } => rotate(workspace, &cluster)?,
+        KeysCommand::ChangePassword { cluster } => change_password(workspace, &cluster)?,
+    }
+    Ok(())
+}
+
+fn encrypt(workspace: &Workspace, cluster: &ClusterRef) -> Result<()> {
+    let keypair_path = workspace.get_deployer_kp_path(cluster);
+    let encrypted_kp_path = workspace.get_encrypted_deployer_kp_path(cluster);
+    if encrypted_kp_path.exists() {
+        return Err(format_err!(
+            "deployer for {} is already encrypted at {}",
+            cluster,
+            encrypted_kp_path.display()
+        ));
+    }
+    let keypair = read_keypair_file(&keypair_path).map_err(|_| {
+        format_err!(
+            "could not read deployer keypair {}; you may need to run `goki init`",
+            keypair_path.display()
+        )
+    })?;
+    let password = read_new_password(PASSWORD_ENV)?;
+    Keystore::encrypt(&keypair, &password)?.write(&encrypted_kp_path)?;
+    std::fs::remove_file(&keypair_path)?;
+    println!(
+        "Encrypted deployer for {} to {}",
+        cluster,
+        encrypted_kp_path.display()
+    );
+    Ok(())
+}
+
+/// Reads and unlocks the encrypted deployer of a cluster.
+fn read_encrypted_deployer(workspace: &Workspace, cluster: &ClusterRef) -> Result<Keypair> {
+    let encrypted_kp_path = workspace.get_encrypted_deployer_kp_path(cluster);
+    if !encrypted_kp_path.exists() {
+        return Err(format_err!(
+            "no encrypted deployer for {} at {}",
+            cluster,
+            encrypted_kp_path.display()
+        ));
+    }
+    let keystore = Keystore::read(&encrypted_kp_path)?;
+    let password = read_password(
+        &format!("Password for {} ({}): ", cluster, keystore.pubkey),
+        PASSWORD_ENV,
+    )?;
+    keystore.decrypt(&password)
+}
+
+fn decrypt(workspace: &Workspace, cluster: &ClusterRef) -> Result<()> {
+    let keypair = read_encrypted_deployer(workspace, cluster)?;
+    let keypair_path = workspace.get_deployer_kp_path(cluster);
+    write_private_keypair(&keypair_path, &keypair)?;
+    std::fs::remove_file(workspace.get_encrypted_deployer_kp_path(cluster))?;
+    println!(
+        "Decrypted deployer for {} to {}",
+        cluster,
+        keypair_path.display()
+    );
+    Ok(())
+}
+
+fn change_password(workspace: &Workspace, cluster: &ClusterRef) -> Result<()> {
+    let keypair = read_encrypted_deployer(workspace, cluster)?;
+    let password = read_new_password(NEW_PASSWORD_ENV)?;
+    Keystore::encrypt(&keypair, &password)?
+        .write(&workspace.get_encrypted_deployer_kp_path(cluster))?;
+    println!("Changed the password of the deployer for {}", cluster);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn new_deployer(
+    workspace: &Workspace,
+    cluster: &ClusterRef,
+    mnemonic: bool,
+    word_count: Option<usize>,
+    derivation_path: Option<String>,
+    vanity: Option<String>,
+    ignore_case: bool,
+    threads: Option<usize>,
+    force: bool,
+) -> Result<()> {
+    let keypair_path = workspace.get_deployer_kp_path(cluster);
+    let encrypted_kp_path = workspace.get_encrypted_deployer_kp_path(cluster);
+    let existing: Vec<&PathBuf> = [&keypair_path, &encrypted_kp_path]
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect();
+    if let Some(path) = existing.first() {
+        if !force {
+            return Err(format_err!(
+                "deployer for {} already exists at {}; pass --force to replace it",
+                cluster,
+                path.display()
+            ));
+        }
+    }
+    // Keep the deployer encrypted if it was.
+    let password = if encrypted_kp_path.exists() {
+        println!("Choose a password for the new deployer.");
+        Some(read_new_password(NEW_PASSWORD_ENV)?)
+    } else {
+        None
+    };
+
+    let keypair = match vanity {
+        Some(prefix) => {
+            let threads = match threads {
+                Some(threads) => threads,
+                None => thread::available_parallelism()?.get(),
+            };
+            grind_vanity_keypair(&prefix, ignore_case, threads.max(1))?
+        }
+        None if mnemonic => new_mnemonic_keypair(
+            word_count.unwrap_or(12),
+            derivation_path
+                .as_deref()
+                .unwrap_or(DEFAULT_DERIVATION_PATH),
+        )?,
+        None => Keypair::new(),
+    };
+
+    // The replaced deployer may still hold funds, so it is archived rather than deleted.
+    for path in existing {
+        let archive_path = workspace.archive_deployer_file(path)?;
+        println!("Archived {} to {}", path.display(), archive_path.display());
+    }
+    std::fs::create_dir_all(workspace.deployer_dir())?;
+    let written_path = match password {
+        Some(password) => {
+            Keystore::encrypt(&keypair, &password)?.write(&encrypted_kp_path)?;
+            &encrypted_kp_path
+        }
+        None => {
+            write_private_keypair(&keypair_path, &keypair)?;
+            &keypair_path
+        }
+    };
+    println!(
+        "Deployer for {}: {}",
+        cluster,
+        keypair.pubkey().to_string().green()
+    );
+    println!("Written to {}", written_path.display());
+    Ok(())
+}
+
+fn rotate(workspace: &Workspace, cluster: &ClusterRef) -> Result<()> {
+    let (old_pubkey, encrypted) = workspace.get_deployer_pubkey(cluster)?.ok_or_else(|| {
+        format_err!(
+            "deployer for {} not found; you may need to run `goki init`",
+            cluster
+        )
+    })?;
+    let rpc_client = workspace.rpc_client(cluster)?;
+    let commitment = workspace.commitment(cluster)?;
+    let balance = rpc_client
+        .get_balance_with_commitment(&old_pubkey, commitment)
+        .map_err(|e| format_err!("could not fetch balance of deployer {}: {}", old_pubkey, e))?
+        .value;
+    let buffers = workspace.get_buffers(cluster, &old_pubkey)?;
+    workspace.confirm_action(
+        cluster,
+        &format!(
+            "Rotating deployer {}, moving {} SOL and {} buffer(s) to a new deployer",
+            old_pubkey,
+            lamports_to_sol(balance),
+            buffers.len()
+        ),
+    )?;
+
+    // Unlock the old deployer and read the new password before anything is changed.
+    let old_kp_path = workspace.get_deployer_kp_path_if_exists(cluster)?;
+    let new_password = if encrypted {
+        println!("Choose a password for the new deployer.");
+        Some(read_new_password(NEW_PASSWORD_ENV)?)
+    } else {
+        None
+    };
+
+    let current_path = if encrypted {
+        workspace.get_encrypted_deployer_kp_path(cluster)
+    } else {
+        workspace.get_deployer_kp_path(cluster)
+    };
+    let archive_path = workspace.archive_deployer_file(&current_path)?;
+    // Encrypted deployers stay unlocked for the rest of the command.
+    let old_wallet = if encrypted {
+        old_kp_path
+    } else {
+        archive_path.clone()
+    }
+    .display()
+    .to_string();
+
+    let new_keypair = Keypair::new();
+    let new_pubkey = new_keypair.pubkey();
+    match new_password {
+        Some(password) => Keystore::encrypt(&new_keypair, &password)?
+            .write(&workspace.get_encrypted_deployer_kp_path(cluster))?,
+        None => {
+            let keypair_path = workspace.get_deployer_kp_path(cluster);
+            write_private_keypair(&keypair_path, &new_keypair)?;
+        }
+    }
+    println!("New deployer for {}: {}", cluster, new_pubkey);
+
+    let ctx = workspace.new_cluster_context(cluster)?;
+    let new_pubkey_str = new_pubkey.to_string();
+    let mut moved_buffers = vec![];
+    let mut failures = vec![];
+    for (buffer, _) in buffers.iter() {
+        let buffer_str = buffer.to_string();
+        match ctx.exec_args(
+            &[
+                "program",
+                "set-buffer-authority",
+                &buffer_str,
+                "--new-buffer-authority",
+                &new_pubkey_str,
+            ],
+            &old_wallet,
+        ) {
+            Ok(_) => moved_buffers.push(buffer),
+            Err(err) => failures.push(format!(
+                "could not set the authority of buffer {}: {}",
+                buffer, err
+            )),
+        }
+    }
+    if balance > 0 {
+        if let Err(err) = ctx.exec_args(
+            &[
+                "transfer",
+                "--allow-unfunded-recipient",
+                &new_pubkey_str,
+                "ALL",
+            ],
+            &old_wallet,
+        ) {
+            failures.push(format!("could not transfer the balance: {}", err));
+        }
+    }
+    let moved = rpc_client
+        .get_balance_with_commitment(&new_pubkey, commitment)
+        .map(|response| response.value)
+        .unwrap_or_default();
+
+    println!();
+    println!("{}", format!("Rotated deployer for {}", cluster).bold());
+    println!("Old deployer: {}", old_pubkey);
+    println!("  archived at {}", archive_path.display());
+    println!("New deployer: {}", new_pubkey.to_string().green());
+    println!("Moved {} SOL", lamports_to_sol(moved));
+    println!(
+        "Moved {} of {} buffer(s)",
+        moved_buffers.len(),
+        buffers.len()
+    );
+    for buffer in moved_buffers {
+        println!("  {}", buffer);
+    }
+    if !failures.is_empty() {
+        for failure in failures.iter() {
+            println!("{} {}", "error:".red(), failure);
+        }
+        return Err(format_err!(
+            "some funds or buffers remain with the old deployer {}; its keypair is archived at {}",
+            old_pubkey,
+            archive_path.display()
+        ));
+    }
+    Ok(())
+}
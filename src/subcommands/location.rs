@@ -0,0 +1,24 @@
+use anyhow::Result;
+use colored::*;
+
+use crate::{
+    cli::LocationCommand,
+    location::{split_checksum, Location},
+    workspace::Workspace,
+};
+
+pub fn process(workspace: &Workspace, command: LocationCommand) -> Result<()> {
+    match command {
+        LocationCommand::Parse { location } => {
+            let (location_str, pinned_sha256) = split_checksum(&location)?;
+            let location: Location = location_str.parse()?;
+            println!("{} {}", "Location:".bold(), location.to_string().green());
+            println!("  Type: {}", location.kind().green());
+            println!("  Source: {}", location.source(workspace)?.green());
+            if let Some(sha256) = pinned_sha256 {
+                println!("  SHA256: {}", sha256.green());
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,9 +1,15 @@
 pub mod airdrop;
 pub mod balance;
+pub mod cache;
+pub mod config;
 pub mod deploy;
 pub mod init;
+pub mod keys;
+pub mod location;
 pub mod pull;
+pub mod registry;
 pub mod show;
 pub mod transfer;
 pub mod upgrade_local;
 pub mod upload_program_buffer;
+pub mod workspace;
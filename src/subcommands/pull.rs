@@ -1,23 +1,39 @@
 use anyhow::Result;
 use colored::*;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::BufReader;
 use std::path::PathBuf;
 use tempfile::NamedTempFile;
 
-use crate::location::fetch_program_file;
+use crate::location::FetchOptions;
 use crate::utils::sha256_digest;
+use crate::workspace::Workspace;
 
-pub async fn process(location: &str, out: Option<PathBuf>) -> Result<()> {
+pub async fn process(
+    workspace: &Workspace,
+    location: &str,
+    out: Option<PathBuf>,
+    resume: bool,
+    fetch_options: &FetchOptions,
+) -> Result<()> {
     let mut temp_out_file = NamedTempFile::new()?;
     let program_file_path = match out.clone() {
         Some(out_path) => {
-            let mut out_file = File::create(&out_path)?;
-            fetch_program_file(&mut out_file, location).await?;
+            let mut out_file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(!resume)
+                .open(&out_path)?;
+            workspace
+                .fetch_program_file(&mut out_file, location, fetch_options)
+                .await?;
             out_path
         }
         None => {
-            fetch_program_file(&mut temp_out_file, location).await?;
+            workspace
+                .fetch_program_file(temp_out_file.as_file_mut(), location, fetch_options)
+                .await?;
             temp_out_file.path().to_path_buf()
         }
     };
@@ -0,0 +1,45 @@
+use anyhow::Result;
+use colored::*;
+
+use crate::{
+    cli::RegistryCommand,
+    location::{parse_spr_ref, registry::RegistryIndex},
+    workspace::Workspace,
+};
+
+pub async fn process(workspace: &Workspace, command: RegistryCommand) -> Result<()> {
+    let index = RegistryIndex::new(&workspace.cfg.registry.index_url);
+    match command {
+        RegistryCommand::Search { query } => {
+            let query = query.to_lowercase();
+            let matches: Vec<_> = index
+                .list_artifacts()?
+                .into_iter()
+                .filter(|artifact| {
+                    artifact.id.to_lowercase().contains(&query)
+                        || artifact.name.to_lowercase().contains(&query)
+                })
+                .collect();
+            if matches.is_empty() {
+                println!("No artifacts found matching {}.", query);
+            }
+            for artifact in matches.iter() {
+                println!(
+                    "{}{}{} {}",
+                    artifact.id.bold(),
+                    "@".bold(),
+                    artifact.tag.bold(),
+                    artifact.name.green()
+                );
+            }
+        }
+        RegistryCommand::Show { id } => {
+            let (id, version) = parse_spr_ref(&id);
+            index
+                .get_artifact(&workspace.http_client()?, id, version)
+                .await?
+                .print_info();
+        }
+    }
+    Ok(())
+}
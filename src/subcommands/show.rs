@@ -1,32 +1,311 @@
-use anchor_client::Cluster;
 use anyhow::{format_err, Result};
 use colored::*;
-use solana_sdk::{signature::read_keypair_file, signer::Signer};
+use serde::Serialize;
+use solana_sdk::native_token::lamports_to_sol;
 
-use crate::workspace::Workspace;
+use crate::{
+    cli::OutputFormat,
+    cluster::ClusterRef,
+    config::interpolate::redact,
+    explorer::LinkKind,
+    policy::{is_signer_uri, UpgradeAuthority},
+    workspace::Workspace,
+};
 
-pub fn process(workspace: &Workspace) -> Result<()> {
+#[derive(Debug, Serialize)]
+struct DeployerInfo {
+    pubkey: String,
+    encrypted: bool,
+    /// Balance in SOL, if it could be fetched.
+    balance: Option<f64>,
+    /// Link to the deployer in the explorer of the cluster.
+    explorer_link: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BufferInfo {
+    address: String,
+    /// Balance in SOL.
+    balance: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct UpgradeAuthorityInfo {
+    /// Path to the keypair, if the authority is a local keypair.
+    keypair: Option<String>,
+    pubkey: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ClusterInfo {
+    cluster: String,
+    url: Option<String>,
+    deployer: Option<DeployerInfo>,
+    /// Buffers whose authority is the deployer, if they could be fetched.
+    buffers: Option<Vec<BufferInfo>>,
+    upgrade_authority: Option<UpgradeAuthorityInfo>,
+    /// Problems found, such as missing keypairs and unreachable RPCs.
+    problems: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct WorkspaceInfo {
+    workspace: String,
+    clusters: Vec<ClusterInfo>,
+}
+
+fn get_upgrade_authority_info(
+    workspace: &Workspace,
+    cluster: &ClusterRef,
+    problems: &mut Vec<String>,
+) -> Option<UpgradeAuthorityInfo> {
+    let authority = match workspace.get_upgrade_authority(cluster) {
+        Ok(authority) => authority?,
+        Err(err) => {
+            problems.push(err.to_string());
+            return None;
+        }
+    };
+    // Resolving a signer URI may need a hardware wallet or a prompt, so it is left out.
+    let pubkey = match &authority {
+        UpgradeAuthority::Keypair(signer) if is_signer_uri(signer) => None,
+        _ => match authority.pubkey() {
+            Ok(pubkey) => Some(pubkey.to_string()),
+            Err(err) => {
+                problems.push(err.to_string());
+                None
+            }
+        },
+    };
+    let keypair = match authority {
+        UpgradeAuthority::Keypair(path) => Some(redact(&path)),
+        UpgradeAuthority::Pubkey(_) => None,
+    };
+    Some(UpgradeAuthorityInfo { keypair, pubkey })
+}
+
+/// Information about a cluster. Problems are reported in [ClusterInfo::problems] rather than
+/// failing, so that they do not hide the other clusters.
+fn get_cluster_info(workspace: &Workspace, cluster: &ClusterRef) -> ClusterInfo {
+    let mut problems = vec![];
+    let url = match workspace.get_cluster_url(cluster) {
+        Ok(url) => Some(redact(url)),
+        Err(err) => {
+            problems.push(err.to_string());
+            None
+        }
+    };
+    let upgrade_authority = get_upgrade_authority_info(workspace, cluster, &mut problems);
+
+    let deployer = match workspace.get_deployer_pubkey(cluster) {
+        Ok(Some(deployer)) => Some(deployer),
+        Ok(None) => {
+            problems.push(format!(
+                "deployer keypair not found at {}; run `goki init` or `goki keys new -c {}`",
+                workspace.get_deployer_kp_path(cluster).display(),
+                cluster
+            ));
+            None
+        }
+        Err(err) => {
+            problems.push(format!("could not read deployer keypair: {}", err));
+            None
+        }
+    };
+    let mut balance = None;
+    let mut buffers = None;
+    if let (Some(_), Some((pubkey, _))) = (url.as_ref(), deployer.as_ref()) {
+        let fetched = workspace.rpc_client(cluster).and_then(|rpc_client| {
+            rpc_client
+                .get_balance_with_commitment(pubkey, workspace.commitment(cluster)?)
+                .map_err(|e| format_err!("{}", e))
+        });
+        match fetched {
+            Ok(response) => {
+                balance = Some(lamports_to_sol(response.value));
+                match workspace.get_buffers(cluster, pubkey) {
+                    Ok(found) => {
+                        buffers = Some(
+                            found
+                                .into_iter()
+                                .map(|(address, lamports)| BufferInfo {
+                                    address: address.to_string(),
+                                    balance: lamports_to_sol(lamports),
+                                })
+                                .collect(),
+                        )
+                    }
+                    Err(err) => problems.push(err.to_string()),
+                }
+            }
+            Err(err) => problems.push(format!("RPC unreachable: {}", redact(&err.to_string()))),
+        }
+    }
+
+    // A missing URL is reported by everything that needs the cluster config.
+    problems.dedup();
+    ClusterInfo {
+        cluster: cluster.to_string(),
+        url,
+        deployer: deployer.map(|(pubkey, encrypted)| DeployerInfo {
+            pubkey: pubkey.to_string(),
+            encrypted,
+            balance,
+            explorer_link: workspace.explorer_link(cluster, LinkKind::Address, &pubkey.to_string()),
+        }),
+        buffers,
+        upgrade_authority,
+        problems,
+    }
+}
+
+fn print_cluster_info(info: &ClusterInfo) {
+    println!();
+    match info.url.as_ref() {
+        Some(url) => println!("{} ({})", info.cluster.bold(), url),
+        None => println!("{}", info.cluster.bold()),
+    }
+    match info.deployer.as_ref() {
+        Some(deployer) => {
+            print!("  Deployer: {}", deployer.pubkey);
+            if deployer.encrypted {
+                print!(" {}", "(encrypted)".dimmed());
+            }
+            println!();
+            if let Some(balance) = deployer.balance {
+                println!("  Balance: {} SOL", balance);
+            }
+            if let Some(link) = deployer.explorer_link.as_ref() {
+                println!("  => Explorer Link: {}", link);
+            }
+        }
+        None => println!("  Deployer: {}", "not found".red()),
+    }
+    match info.upgrade_authority.as_ref() {
+        Some(UpgradeAuthorityInfo {
+            keypair: Some(keypair),
+            pubkey,
+        }) => println!(
+            "  Upgrade authority: {} ({})",
+            pubkey.as_deref().unwrap_or("unreadable"),
+            keypair
+        ),
+        Some(UpgradeAuthorityInfo { pubkey, .. }) => println!(
+            "  Upgrade authority: {}",
+            pubkey.as_deref().unwrap_or_default()
+        ),
+        None => println!("  Upgrade authority: {}", "not configured".dimmed()),
+    }
+    if let Some(buffers) = info.buffers.as_ref() {
+        if buffers.is_empty() {
+            println!("  Buffers: none");
+        } else {
+            println!("  Buffers:");
+            for buffer in buffers {
+                println!("    {} ({} SOL)", buffer.address, buffer.balance);
+            }
+        }
+    }
+    for problem in info.problems.iter() {
+        println!("  {} {}", "Warning:".yellow(), problem);
+    }
+}
+
+pub fn process(
+    workspace: &Workspace,
+    clusters: Vec<ClusterRef>,
+    output: OutputFormat,
+) -> Result<()> {
     let deployer_dir = workspace.deployer_dir();
     if !deployer_dir.exists() {
         return Err(format_err!("{} does not exist", deployer_dir.display()));
     }
 
-    println!("{}", "Deployers:".bold());
-    for cluster in [Cluster::Devnet, Cluster::Testnet, Cluster::Mainnet].iter() {
-        let keypair_path = workspace.get_deployer_kp_path(cluster);
-        if keypair_path.exists() {
-            let kp = read_keypair_file(keypair_path)
-                .map_err(|_| format_err!("could not read keypair"))?;
-            let pubkey = kp.pubkey();
-            println!("{}: {}", cluster, pubkey);
-            println!(
-                "=> Solana Explorer Link: https://explorer.solana.com/address/{}?cluster={}",
-                pubkey, cluster
-            );
-        } else {
-            println!("{}: {}", cluster, "not found".red());
-        };
+    let clusters = if clusters.is_empty() {
+        workspace.clusters()?
+    } else {
+        clusters
+    };
+    let infos = clusters
+        .iter()
+        .map(|cluster| get_cluster_info(workspace, cluster))
+        .collect::<Vec<_>>();
+
+    match output {
+        OutputFormat::Json => {
+            let info = WorkspaceInfo {
+                workspace: workspace.path.display().to_string(),
+                clusters: infos,
+            };
+            println!("{}", serde_json::to_string_pretty(&info)?);
+        }
+        OutputFormat::Text => {
+            println!("{}", "Deployers:".bold());
+            for info in infos.iter() {
+                print_cluster_info(info);
+            }
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, str::FromStr};
+
+    #[test]
+    fn collects_problems_per_cluster() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing_authority = dir.path().join("missing.json");
+        fs::write(
+            dir.path().join("Goki.toml"),
+            format!(
+                "version = 2\n\n[clusters.private]\n\n\
+                 [clusters.staging]\nurl = \"http://127.0.0.1:1\"\nupgrade_authority = {:?}\n\n\
+                 [clusters.ledger]\nurl = \"http://127.0.0.1:1\"\nupgrade_authority = \"usb://ledger\"\n",
+                missing_authority.display().to_string()
+            ),
+        )
+        .unwrap();
+        let workspace_dir = dir.path().join(".goki");
+        fs::create_dir_all(workspace_dir.join("deployers")).unwrap();
+        fs::write(
+            workspace_dir.join("deployers/staging.json"),
+            "not a keypair",
+        )
+        .unwrap();
+        let workspace = Workspace::load(Some(&workspace_dir), &[]).unwrap();
+        let info = |name: &str| get_cluster_info(&workspace, &ClusterRef::from_str(name).unwrap());
+
+        // The missing URL is reported once, although the upgrade authority needs it too.
+        let private = info("private");
+        assert_eq!(private.url, None);
+        assert!(private.upgrade_authority.is_none());
+        assert!(private.deployer.is_none());
+        assert_eq!(private.problems.len(), 2, "{:?}", private.problems);
+        assert!(private.problems[0].starts_with("cluster private has no URL"));
+        assert!(private.problems[1].starts_with("deployer keypair not found"));
+        assert!(private.problems[1].ends_with("`goki keys new -c private`"));
+
+        let staging = info("staging");
+        assert_eq!(staging.url.as_deref(), Some("http://127.0.0.1:1"));
+        assert!(staging.deployer.is_none());
+        assert_eq!(staging.upgrade_authority.unwrap().pubkey, None);
+        assert_eq!(staging.problems.len(), 2, "{:?}", staging.problems);
+        assert!(staging.problems[0].starts_with(&format!(
+            "could not read upgrade authority {}",
+            missing_authority.display()
+        )));
+        assert!(staging.problems[1].starts_with("could not read deployer keypair"));
+
+        // Signer URIs are shown without being resolved.
+        let ledger = info("ledger");
+        let authority = ledger.upgrade_authority.unwrap();
+        assert_eq!(authority.keypair.as_deref(), Some("usb://ledger"));
+        assert_eq!(authority.pubkey, None);
+        assert_eq!(ledger.problems.len(), 1, "{:?}", ledger.problems);
+        assert!(ledger.problems[0].starts_with("deployer keypair not found"));
+    }
+}
@@ -1,19 +1,40 @@
+use anyhow::{format_err, Result};
+use solana_sdk::native_token::sol_to_lamports;
+
+use crate::cluster::ClusterRef;
 use crate::workspace::Workspace;
-use anchor_client::Cluster;
-use anyhow::Result;
+
+/// Parses an amount of SOL into lamports. `ALL` is the entire balance.
+fn parse_lamports(amount: &str) -> Result<u64> {
+    if amount.eq_ignore_ascii_case("all") {
+        return Ok(u64::MAX);
+    }
+    let sol: f64 = amount
+        .parse()
+        .map_err(|_| format_err!("invalid amount {}", amount))?;
+    Ok(sol_to_lamports(sol))
+}
 
 pub fn process(
     workspace: &Workspace,
-    cluster: &Cluster,
+    cluster: &ClusterRef,
     from_raw: &str,
     to_raw: &str,
     amount: &str,
 ) -> Result<()> {
     let ctx = workspace.new_cluster_context(cluster)?;
     let to = ctx.parse_wallet_alias(to_raw)?;
-    ctx.exec_args(
-        &["transfer", &to, amount],
-        &ctx.parse_wallet_alias(from_raw)?,
+    let from = ctx.parse_wallet_alias(from_raw)?;
+    if from_raw == "deployer" {
+        workspace.check_deployer_balance(cluster, parse_lamports(amount)?)?;
+    }
+    workspace.confirm_action(
+        cluster,
+        &format!(
+            "Transferring {} SOL from {} to {}",
+            amount, from_raw, to_raw
+        ),
     )?;
+    ctx.exec_args(&["transfer", &to, amount], &from)?;
     Ok(())
 }
@@ -1,4 +1,3 @@
-use anchor_client::Cluster;
 use anyhow::{format_err, Result};
 use colored::*;
 use solana_sdk::pubkey::Pubkey;
@@ -7,22 +6,35 @@ use std::io::BufReader;
 use std::str::FromStr;
 use tempfile::NamedTempFile;
 
+use crate::cluster::ClusterRef;
+use crate::policy::{signer_pubkey, ProgramAccounts, UpgradeAuthority};
+use crate::program::ResolvedProgram;
 use crate::utils::{gen_new_keypair, sha256_digest};
-use crate::{location::fetch_program_file, workspace::Workspace};
+use crate::{location::FetchOptions, workspace::Workspace};
 
 pub async fn process(
     workspace: &Workspace,
-    cluster: Cluster,
+    cluster: ClusterRef,
     upgrade_authority_kp_provided: Option<String>,
-    location_or_buffer: String,
-    program_id: String,
+    program: &ResolvedProgram,
+    fetch_options: &FetchOptions,
 ) -> Result<()> {
+    let location_or_buffer = program.location()?;
+    let program_id = program.program_id()?;
+    workspace.check_local_signing(&cluster)?;
     let upgrade_authority_kp: String = match &upgrade_authority_kp_provided {
         Some(kp_path) => kp_path.clone(),
-        None => match &workspace.cfg.upgrade_authority_keypair {
-            Some(kp_path) => kp_path.clone(),
+        None => match workspace.get_upgrade_authority(&cluster)? {
+            Some(UpgradeAuthority::Keypair(kp_path)) => kp_path,
+            Some(UpgradeAuthority::Pubkey(pubkey)) => {
+                return Err(format_err!(
+                    "the upgrade authority of cluster {} is the public key {}; pass --upgrade-authority-keypair or upgrade through the upgrade authority",
+                    cluster,
+                    pubkey
+                ));
+            }
             None => {
-                if cluster == Cluster::Mainnet {
+                if cluster.is_mainnet() {
                     return Err(format_err!(
                         "Must specify the --upgrade_authority_keypair on mainnet."
                     ));
@@ -32,12 +44,32 @@ pub async fn process(
             }
         },
     };
+    if program.upgrade_authority.is_some() {
+        program.check_upgrade_authority(&signer_pubkey(&upgrade_authority_kp)?)?;
+    }
 
-    let buffer_key: Pubkey = match Pubkey::from_str(location_or_buffer.as_str()) {
-        Ok(buffer) => buffer,
+    let buffer_key: Pubkey = match Pubkey::from_str(location_or_buffer) {
+        Ok(buffer) => {
+            if fetch_options.expected_sha256.is_some() {
+                return Err(format_err!(
+                    "--expected-sha256 cannot be used with an already uploaded buffer"
+                ));
+            }
+            workspace.confirm_action(
+                &cluster,
+                &format!("Upgrading program {} to buffer {}", program_id, buffer),
+            )?;
+            buffer
+        }
         Err(_) => {
             let mut program_file = NamedTempFile::new()?;
-            fetch_program_file(&mut program_file, location_or_buffer.as_str()).await?;
+            workspace
+                .fetch_program_file(
+                    program_file.as_file_mut(),
+                    location_or_buffer,
+                    fetch_options,
+                )
+                .await?;
 
             let input = File::open(program_file.path())?;
             let mut reader = BufReader::new(input);
@@ -46,6 +78,18 @@ pub async fn process(
             println!("Size (bytes): {}", program_file_size.to_string().green());
             println!("SHA256: {}", program_file_digest.green());
 
+            workspace.check_deployer_balance(
+                &cluster,
+                workspace.program_rent(&cluster, program_file_size, ProgramAccounts::Buffer)?,
+            )?;
+            workspace.confirm_action(
+                &cluster,
+                &format!(
+                    "Upgrading program {} to {} (SHA256 {})",
+                    program_id, location_or_buffer, program_file_digest
+                ),
+            )?;
+
             let mut buffer_kp_file = NamedTempFile::new()?;
             let buffer_key = gen_new_keypair(&mut buffer_kp_file)?;
 
@@ -56,7 +100,7 @@ pub async fn process(
         }
     };
 
-    workspace.upgrade(&cluster, &upgrade_authority_kp, &buffer_key, &program_id)?;
+    workspace.upgrade(&cluster, &upgrade_authority_kp, &buffer_key, program_id)?;
 
     Ok(())
 }
@@ -1,20 +1,24 @@
-use anchor_client::Cluster;
 use anyhow::format_err;
 use anyhow::Result;
 use colored::*;
 use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::read_keypair_file;
 use solana_sdk::signature::Signer;
 use std::fs::File;
 use std::io::BufReader;
+use std::str::FromStr;
 use tempfile::NamedTempFile;
 
+use crate::cluster::ClusterRef;
+use crate::policy::ProgramAccounts;
+use crate::program::ResolvedProgram;
 use crate::solana_cmd::new_solana_cmd;
 use crate::utils::exec_command_with_output;
 use crate::utils::gen_new_keypair;
 use crate::utils::print_header;
 use crate::utils::sha256_digest;
-use crate::{location::fetch_program_file, workspace::Workspace};
+use crate::{location::FetchOptions, workspace::Workspace};
 
 #[derive(Serialize, Deserialize)]
 struct ProgramInfo {
@@ -23,14 +27,18 @@ struct ProgramInfo {
 
 pub async fn process(
     workspace: &Workspace,
-    cluster: Cluster,
-    location: String,
-    program_id: String,
+    cluster: ClusterRef,
+    program: &ResolvedProgram,
+    fetch_options: &FetchOptions,
 ) -> Result<()> {
+    let location = program.location()?;
+    let program_id = program.program_id()?;
     let deployer_kp_path = workspace.get_deployer_kp_path_if_exists(&cluster)?;
 
     let mut program_file = NamedTempFile::new()?;
-    fetch_program_file(&mut program_file, location.as_str()).await?;
+    workspace
+        .fetch_program_file(program_file.as_file_mut(), location, fetch_options)
+        .await?;
 
     let input = File::open(program_file.path())?;
     let mut reader = BufReader::new(input);
@@ -55,15 +63,28 @@ pub async fn process(
     workspace.add_cluster_args(cmd, &cluster)?;
     let program_info_output = exec_command_with_output(
         cmd.args(["program", "show"])
-            .arg(&program_id)
+            .arg(program_id)
             .args(["--output", "json-compact"]),
     )?;
     let program_info: ProgramInfo = serde_json::from_str(program_info_output.as_str())?;
 
     println!("Program ID: {}", program_id);
     println!("Program authority: {}", program_info.authority);
+    program.check_upgrade_authority(&Pubkey::from_str(&program_info.authority)?)?;
     println!("Buffer key: {}", buffer_key);
 
+    workspace.check_deployer_balance(
+        &cluster,
+        workspace.program_rent(&cluster, program_file_size, ProgramAccounts::Buffer)?,
+    )?;
+    workspace.confirm_action(
+        &cluster,
+        &format!(
+            "Uploading a buffer for program {} (SHA256 {})",
+            program_id, program_file_digest
+        ),
+    )?;
+
     print_header("Writing buffer");
 
     workspace.write_buffer(&cluster, program_file.path(), buffer_kp_file.path())?;
@@ -0,0 +1,350 @@
+use anyhow::{format_err, Result};
+use colored::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bundle::{read_keypair_pubkey, Bundle, BundledKeypair, PASSWORD_ENV},
+    cli::WorkspaceCommand,
+    config::{find_workspace_config, CONFIG_FILE_NAME},
+    keystore::{keystore_path, read_new_password, read_password, write_private_file},
+    utils::format_timestamp,
+    workspace::{
+        archive_deployer_file, config_start_dir, new_config_dir, Workspace, DEFAULT_WORKSPACE_DIR,
+    },
+};
+
+fn print_deployers(bundle: &Bundle) {
+    for (name, keypair) in bundle.deployers.iter() {
+        if keypair.encrypted {
+            println!("{}: {} {}", name, keypair.pubkey, "(encrypted)".dimmed());
+        } else {
+            println!("{}: {}", name, keypair.pubkey);
+        }
+    }
+}
+
+fn export(workspace: &Workspace, out: &Path, force: bool) -> Result<()> {
+    if out.exists() && !force {
+        return Err(format_err!(
+            "{} already exists; pass --force to overwrite it",
+            out.display()
+        ));
+    }
+    let bundle = Bundle::collect(workspace)?;
+    let password = read_new_password(PASSWORD_ENV)?;
+    bundle.write(out, &password)?;
+    println!(
+        "Exported {} and {} keypair(s) to {}",
+        workspace.cfg_path.display(),
+        bundle.deployers.len(),
+        out.display()
+    );
+    print_deployers(&bundle);
+    Ok(())
+}
+
+/// The plaintext path of a bundled keypair, whether or not it is encrypted.
+fn plaintext_path(path: &Path, keypair: &BundledKeypair) -> PathBuf {
+    if !keypair.encrypted {
+        return path.to_path_buf();
+    }
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = file_name
+        .strip_suffix(".enc.json")
+        .unwrap_or(&file_name)
+        .to_string();
+    path.with_file_name(format!("{}.json", stem))
+}
+
+/// What importing a bundle changes in a workspace.
+struct ImportPlan<'a> {
+    /// Whether to write the bundled `Goki.toml`.
+    write_config: bool,
+    /// Keypairs to write, with the existing files they replace.
+    to_write: Vec<(PathBuf, &'a BundledKeypair, Vec<PathBuf>)>,
+    /// Number of bundled keypairs that are already present.
+    unchanged: usize,
+    /// Existing files that differ from the bundled ones.
+    conflicts: Vec<String>,
+}
+
+/// Compares a bundle with the existing workspace, without changing anything.
+fn plan_import<'a>(
+    bundle: &'a Bundle,
+    existing_cfg_path: Option<&Path>,
+    deployer_dir: &Path,
+    force: bool,
+) -> Result<ImportPlan<'a>> {
+    let mut conflicts = vec![];
+    let write_config = match existing_cfg_path {
+        Some(existing) if fs::read_to_string(existing)? != bundle.config => {
+            conflicts.push(format!(
+                "{} differs from the bundled {}",
+                existing.display(),
+                CONFIG_FILE_NAME
+            ));
+            force
+        }
+        Some(_) => false,
+        None => true,
+    };
+    let mut to_write = vec![];
+    let mut unchanged = 0;
+    for (name, keypair) in bundle.deployers.iter() {
+        let target = Bundle::deployer_path(deployer_dir, name)?;
+        // A keypair may exist either in plaintext or encrypted.
+        let plaintext = plaintext_path(&target, keypair);
+        let mut replaced = vec![];
+        let mut present = false;
+        for existing in [keystore_path(&plaintext), plaintext] {
+            if !existing.exists() {
+                continue;
+            }
+            let pubkey = read_keypair_pubkey(&existing)?;
+            if pubkey.to_string() == keypair.pubkey {
+                present = true;
+            } else {
+                conflicts.push(format!(
+                    "{} is {}, but the bundle has {}",
+                    existing.display(),
+                    pubkey,
+                    keypair.pubkey
+                ));
+                replaced.push(existing);
+            }
+        }
+        if present && replaced.is_empty() {
+            unchanged += 1;
+        } else {
+            to_write.push((target, keypair, replaced));
+        }
+    }
+    Ok(ImportPlan {
+        write_config,
+        to_write,
+        unchanged,
+        conflicts,
+    })
+}
+
+fn import(path: Option<&Path>, bundle_path: &Path, force: bool) -> Result<()> {
+    let bundle = Bundle::read(bundle_path, || {
+        read_password("Bundle password: ", PASSWORD_ENV)
+    })?;
+    println!(
+        "Bundle created with goki {} at {}",
+        bundle.goki_version,
+        format_timestamp(bundle.created_at)
+    );
+
+    let existing_cfg_path = find_workspace_config(&config_start_dir(path)?);
+    let cfg_path = match existing_cfg_path.as_ref() {
+        Some(cfg_path) => cfg_path.clone(),
+        None => new_config_dir(path)?.join(CONFIG_FILE_NAME),
+    };
+    let workspace_dir = match path {
+        Some(path) => path.to_path_buf(),
+        None => cfg_path
+            .parent()
+            .expect("config file must be in a directory")
+            .join(DEFAULT_WORKSPACE_DIR),
+    };
+    let deployer_dir = workspace_dir.join("deployers/");
+
+    // Find conflicts before changing anything.
+    let ImportPlan {
+        write_config,
+        to_write,
+        unchanged,
+        conflicts,
+    } = plan_import(&bundle, existing_cfg_path.as_deref(), &deployer_dir, force)?;
+    if !conflicts.is_empty() {
+        for conflict in conflicts.iter() {
+            println!("{} {}", "conflict:".red(), conflict);
+        }
+        if !force {
+            return Err(format_err!(
+                "found {} conflict(s) with the existing workspace; pass --force to replace them with the bundled ones",
+                conflicts.len()
+            ));
+        }
+    }
+
+    if write_config {
+        if let Some(cfg_dir) = cfg_path.parent() {
+            fs::create_dir_all(cfg_dir)?;
+        }
+        fs::write(&cfg_path, &bundle.config)?;
+        println!("Wrote {}", cfg_path.display());
+    }
+    // The bundled config is not loaded, since it may refer to environment variables that
+    // are not set yet.
+    for (target, keypair, replaced) in to_write.iter() {
+        for existing in replaced {
+            let archive_path = archive_deployer_file(&deployer_dir, existing)?;
+            println!(
+                "Archived {} to {}",
+                existing.display(),
+                archive_path.display()
+            );
+        }
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_private_file(target, keypair.contents.as_bytes())?;
+    }
+    println!(
+        "Imported {} keypair(s) into {}; {} already present",
+        to_write.len(),
+        workspace_dir.display(),
+        unchanged
+    );
+    print_deployers(&bundle);
+    Ok(())
+}
+
+pub fn process(path: Option<&Path>, overrides: &[String], command: WorkspaceCommand) -> Result<()> {
+    match command {
+        WorkspaceCommand::Export { out, force } => {
+            let workspace = Workspace::load(path, overrides)?;
+            export(&workspace, &out, force)?;
+        }
+        WorkspaceCommand::Import { bundle, force } => {
+            import(path, &bundle, force)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{write_keypair_file, Keypair, Signer};
+    use std::collections::BTreeMap;
+
+    fn bundled(pubkey: &str, encrypted: bool) -> BundledKeypair {
+        BundledKeypair {
+            pubkey: pubkey.to_string(),
+            encrypted,
+            contents: String::new(),
+        }
+    }
+
+    fn bundle(deployers: &[(&str, BundledKeypair)]) -> Bundle {
+        Bundle {
+            created_at: 0,
+            goki_version: "0.0.0".to_string(),
+            config: "version = 2\n".to_string(),
+            deployers: deployers
+                .iter()
+                .map(|(name, keypair)| (name.to_string(), keypair.clone()))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    fn write_keypair(path: &Path) -> String {
+        let keypair = Keypair::new();
+        write_keypair_file(&keypair, path).unwrap();
+        keypair.pubkey().to_string()
+    }
+
+    #[test]
+    fn imports_everything_into_new_workspaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let deployer_dir = dir.path().join("deployers");
+        let bundle = bundle(&[
+            ("devnet.json", bundled("a", false)),
+            ("archive/devnet-1.json", bundled("b", false)),
+        ]);
+
+        let plan = plan_import(&bundle, None, &deployer_dir, false).unwrap();
+        assert!(plan.write_config);
+        assert!(plan.conflicts.is_empty());
+        assert_eq!(plan.unchanged, 0);
+        let targets: Vec<&PathBuf> = plan.to_write.iter().map(|(target, _, _)| target).collect();
+        assert_eq!(
+            targets,
+            vec![
+                &deployer_dir.join("archive").join("devnet-1.json"),
+                &deployer_dir.join("devnet.json"),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_conflicts_with_existing_workspaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg_path = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(&cfg_path, "version = 1\n").unwrap();
+        let deployer_dir = dir.path().join("deployers");
+        fs::create_dir_all(&deployer_dir).unwrap();
+        let devnet = write_keypair(&deployer_dir.join("devnet.json"));
+        let mainnet = write_keypair(&deployer_dir.join("mainnet.json"));
+        let testnet = write_keypair(&deployer_dir.join("testnet.json"));
+        let other = Keypair::new().pubkey().to_string();
+        let bundle = bundle(&[
+            ("devnet.json", bundled(&devnet, false)),
+            ("mainnet.json", bundled(&other, false)),
+            // The plaintext keypair is replaced by the bundled encrypted one.
+            ("testnet.enc.json", bundled(&other, true)),
+            ("localnet.json", bundled(&other, false)),
+        ]);
+
+        let plan = plan_import(&bundle, Some(&cfg_path), &deployer_dir, false).unwrap();
+        assert!(!plan.write_config);
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(
+            plan.conflicts,
+            vec![
+                format!("{} differs from the bundled Goki.toml", cfg_path.display()),
+                format!(
+                    "{} is {}, but the bundle has {}",
+                    deployer_dir.join("mainnet.json").display(),
+                    mainnet,
+                    other
+                ),
+                format!(
+                    "{} is {}, but the bundle has {}",
+                    deployer_dir.join("testnet.json").display(),
+                    testnet,
+                    other
+                ),
+            ]
+        );
+        let to_write: Vec<(PathBuf, Vec<PathBuf>)> = plan
+            .to_write
+            .into_iter()
+            .map(|(target, _, replaced)| (target, replaced))
+            .collect();
+        assert_eq!(
+            to_write,
+            vec![
+                (deployer_dir.join("localnet.json"), vec![]),
+                (
+                    deployer_dir.join("mainnet.json"),
+                    vec![deployer_dir.join("mainnet.json")]
+                ),
+                (
+                    deployer_dir.join("testnet.enc.json"),
+                    vec![deployer_dir.join("testnet.json")]
+                ),
+            ]
+        );
+
+        let plan = plan_import(&bundle, Some(&cfg_path), &deployer_dir, true).unwrap();
+        assert!(plan.write_config);
+    }
+
+    #[test]
+    fn rejects_keypairs_outside_of_the_deployer_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = bundle(&[("../Goki.toml", bundled("a", false))]);
+        assert!(plan_import(&bundle, None, &dir.path().join("deployers"), true).is_err());
+    }
+}
@@ -8,13 +8,14 @@ use sha2::{Digest, Sha256};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signer;
 use std::{
-    fs::File,
     io::{self, Read, Write},
     path::Path,
     process::{Command, Output, Stdio},
     string::String,
 };
 
+use crate::{config::interpolate::redact, keystore::write_private_keypair};
+
 /// Generates a keypair and writes it to the [Write].
 pub fn gen_new_keypair<W: Write>(write: &mut W) -> Result<Pubkey> {
     let new_keypair = solana_sdk::signer::keypair::Keypair::new();
@@ -24,11 +25,11 @@ pub fn gen_new_keypair<W: Write>(write: &mut W) -> Result<Pubkey> {
     Ok(new_key)
 }
 
-/// Generates a keypair at a [Path].
+/// Generates a keypair at a [Path], readable only by the current user.
 pub fn gen_keypair_file(path: &Path) -> Result<Pubkey> {
-    let mut file = File::create(path)?;
-    let pubkey = gen_new_keypair(&mut file)?;
-    Ok(pubkey)
+    let keypair = solana_sdk::signer::keypair::Keypair::new();
+    write_private_keypair(path, &keypair)?;
+    Ok(keypair.pubkey())
 }
 
 pub fn print_header(header: &'static str) {
@@ -58,7 +59,6 @@ fn rem_first_and_last(value: &str) -> &str {
 
 fn fmt_command(command: &Command) -> String {
     rem_first_and_last(&format!("{:?}", command))
-        .to_string()
         .split("\" \"")
         .map(|s| {
             if s.contains('?') || s.contains('!') {
@@ -70,11 +70,12 @@ fn fmt_command(command: &Command) -> String {
         .join(" ")
 }
 
+/// Prints a command, redacting secrets interpolated into the config.
 fn print_command(command: &Command) {
     println!(
         "{} {}",
         "=> Running command:".bold(),
-        fmt_command(command).yellow()
+        redact(&fmt_command(command)).yellow()
     );
 }
 
@@ -90,6 +91,42 @@ pub fn exec_command(command: &mut Command) -> Result<Output> {
     Ok(exit)
 }
 
+/// Executes a command like [exec_command], also capturing its stdout as it is printed.
+pub fn exec_command_tee(command: &mut Command) -> Result<Output> {
+    print_command(command);
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| format_err!("Error deploying: {}", e.to_string()))?;
+    let mut captured = vec![];
+    if let Some(mut child_stdout) = child.stdout.take() {
+        let stdout = &mut io::stdout();
+        let mut buf = [0; 4096];
+        loop {
+            let len = child_stdout.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            stdout.write_all(&buf[..len])?;
+            stdout.flush()?;
+            captured.extend_from_slice(&buf[..len]);
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format_err!(
+            "Command returned with exit code {}",
+            status.code().unwrap_or_default()
+        ));
+    }
+    Ok(Output {
+        status,
+        stdout: captured,
+        stderr: vec![],
+    })
+}
+
 /// Executes a command, returning the captured stdout.
 pub fn exec_command_with_output(command: &mut Command) -> Result<String> {
     print_command(command);
@@ -98,11 +135,40 @@ pub fn exec_command_with_output(command: &mut Command) -> Result<String> {
         .output()
         .map_err(|e| format_err!("Error deploying: {}", e.to_string()))?;
     if !exit.status.success() {
-        std::process::exit(exit.status.code().unwrap_or(1));
+        return Err(format_err!(
+            "Command returned with exit code {}",
+            exit.status.code().unwrap_or_default()
+        ));
     }
     Ok(String::from_utf8(exit.stdout)?)
 }
 
+/// Formats seconds since the Unix epoch as an RFC 3339 date and time in UTC.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+    // Converts days since the epoch to a civil date in the proleptic Gregorian calendar.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
 pub fn sha256_digest<R: Read>(reader: &mut R) -> Result<(u64, String)> {
     let mut hasher = Sha256::new();
     let num_bytes = io::copy(reader, &mut hasher)?;
@@ -121,3 +187,27 @@ pub fn pause(message: &str) {
     // Read a single byte and discard
     let _ = stdin.read(&mut [0u8]).unwrap();
 }
+
+/// Asks a yes/no question on the terminal. Anything other than `y` or `yes` is a no.
+pub fn confirm(prompt: &str) -> Result<bool> {
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", prompt)?;
+    stdout.flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_timestamps_in_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(1_792_320_421), "2026-10-18T10:47:01Z");
+        assert_eq!(format_timestamp(4_107_542_399), "2100-02-28T23:59:59Z");
+    }
+}
@@ -1,42 +1,142 @@
 use anchor_client::Cluster;
 use anyhow::{format_err, Result};
+use solana_sdk::{pubkey::Pubkey, signature::read_keypair_file, signer::Signer};
 use std::{
     ffi::OsStr,
     path::{Path, PathBuf},
     process::{Command, Output},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use crate::{config::Config, solana_cmd::new_solana_cmd, utils::exec_command};
+use crate::{
+    cluster::ClusterRef,
+    config::{validate::Diagnostic, Config, ConfigLayers},
+    keystore::{self, keystore_path, Keystore, KEYSTORE_EXTENSION},
+    policy::UpgradeAuthority,
+    solana_cmd::new_solana_cmd,
+};
+
+/// Directory of the workspace, relative to `Goki.toml`, if not specified.
+pub const DEFAULT_WORKSPACE_DIR: &str = ".goki/";
 
 #[derive(Clone, Debug, Default)]
 pub struct Workspace {
     pub path: PathBuf,
     pub cfg: Config,
+    /// Path to the `Goki.toml` of the workspace.
+    pub cfg_path: PathBuf,
+    /// `<key>=<value>` overrides of the config passed on the command line.
+    pub overrides: Vec<String>,
+}
+
+/// Directory to start searching for `Goki.toml` in.
+pub(crate) fn config_start_dir(path: Option<&Path>) -> Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    Ok(match path {
+        Some(path) => cwd.join(path),
+        None => cwd,
+    })
+}
+
+/// Directory to create a new `Goki.toml` in: next to the workspace directory if one is
+/// given, and the current directory otherwise.
+pub(crate) fn new_config_dir(path: Option<&Path>) -> Result<PathBuf> {
+    let start_dir = config_start_dir(path)?;
+    Ok(match path {
+        Some(_) => {
+            let workspace_dir: PathBuf = start_dir.components().collect();
+            workspace_dir
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or(workspace_dir)
+        }
+        None => start_dir,
+    })
+}
+
+/// Moves a deployer keypair file to `archive/` in `deployer_dir`, adding a timestamp to its name,
+/// and a counter if an archive with that name already exists.
+pub fn archive_deployer_file(deployer_dir: &Path, path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| format_err!("invalid keypair path {}", path.display()))?;
+    let (stem, extension) = match file_name.strip_suffix(&format!(".{}", KEYSTORE_EXTENSION)) {
+        Some(stem) => (stem, KEYSTORE_EXTENSION),
+        None => (file_name.strip_suffix(".json").unwrap_or(file_name), "json"),
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let archive_dir = deployer_dir.join("archive");
+    std::fs::create_dir_all(&archive_dir)?;
+    // Never replace an earlier archive of the same keypair made within the same second.
+    let mut archive_path = archive_dir.join(format!("{}-{}.{}", stem, timestamp, extension));
+    let mut counter = 1;
+    while archive_path.exists() {
+        archive_path =
+            archive_dir.join(format!("{}-{}-{}.{}", stem, timestamp, counter, extension));
+        counter += 1;
+    }
+    std::fs::rename(path, &archive_path)?;
+    Ok(archive_path)
 }
 
 impl Workspace {
-    pub fn load(path: &Path) -> Result<Workspace> {
-        let cfg = Config::discover()?.expect("Goki.toml not found; please run `goki init`");
+    /// Loads the [ConfigLayers] of the workspace at `path`.
+    ///
+    /// `Goki.toml` is searched for in the workspace directory and its parents if a
+    /// path is given, and in the current directory and its parents otherwise.
+    pub fn load_config(path: Option<&Path>, overrides: &[String]) -> Result<ConfigLayers> {
+        ConfigLayers::load(&config_start_dir(path)?, overrides)
+    }
+
+    /// Loads the configuration like [Workspace::load_config], returning the problems
+    /// found in it instead of failing.
+    pub fn check_config(
+        path: Option<&Path>,
+        overrides: &[String],
+    ) -> Result<(ConfigLayers, Vec<Diagnostic>)> {
+        ConfigLayers::load_unchecked(&config_start_dir(path)?, overrides)
+    }
+
+    /// Loads the workspace at `path`, which defaults to `.goki/` next to `Goki.toml`.
+    pub fn load(path: Option<&Path>, overrides: &[String]) -> Result<Workspace> {
+        let layers = Workspace::load_config(path, overrides)?;
+        let cfg_path = layers
+            .workspace_config_path
+            .clone()
+            .ok_or_else(|| format_err!("Goki.toml not found; please run `goki init`"))?;
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => cfg_path
+                .parent()
+                .expect("config file must be in a directory")
+                .join(DEFAULT_WORKSPACE_DIR),
+        };
         Ok(Workspace {
-            path: path.into(),
-            cfg: cfg.into_inner(),
+            path,
+            cfg: layers.config()?,
+            cfg_path,
+            overrides: overrides.to_vec(),
         })
     }
 
     pub fn reload(&self) -> Result<Workspace> {
-        Workspace::load(&self.path)
+        Workspace::load(Some(&self.path), &self.overrides)
     }
 
     pub fn deployer_dir(&self) -> PathBuf {
         self.path.join("deployers/")
     }
 
-    pub fn get_deployer_kp_path(&self, cluster: &Cluster) -> PathBuf {
+    pub fn get_deployer_kp_path(&self, cluster: &ClusterRef) -> PathBuf {
         let deployer_dir = self.deployer_dir();
         deployer_dir.join(format!("{}.json", cluster))
     }
 
-    pub fn get_deployer_kp_path_if_exists(&self, cluster: &Cluster) -> Result<PathBuf> {
+    pub fn get_deployer_kp_path_if_exists(&self, cluster: &ClusterRef) -> Result<PathBuf> {
         let deployer_dir = self.deployer_dir();
         if !deployer_dir.exists() {
             return Err(format_err!(
@@ -45,58 +145,119 @@ impl Workspace {
             ));
         }
         let deployer_kp_path = deployer_dir.join(format!("{}.json", cluster));
-        if !deployer_kp_path.exists() {
-            return Err(format_err!(
-                "Deployer not found at {}; you may need to run `goki init`",
-                deployer_kp_path.display()
-            ));
+        if deployer_kp_path.exists() {
+            return Ok(deployer_kp_path);
+        }
+        let encrypted_kp_path = keystore_path(&deployer_kp_path);
+        if encrypted_kp_path.exists() {
+            return keystore::unlock(&encrypted_kp_path);
         }
-        Ok(deployer_kp_path)
+        Err(format_err!(
+            "Deployer not found at {}; you may need to run `goki init`",
+            deployer_kp_path.display()
+        ))
+    }
+
+    /// Moves a deployer keypair file to the archive of the workspace. See [archive_deployer_file].
+    pub fn archive_deployer_file(&self, path: &Path) -> Result<PathBuf> {
+        archive_deployer_file(&self.deployer_dir(), path)
+    }
+
+    /// Path to the encrypted deployer keypair of a cluster.
+    pub fn get_encrypted_deployer_kp_path(&self, cluster: &ClusterRef) -> PathBuf {
+        keystore_path(&self.get_deployer_kp_path(cluster))
     }
 
-    pub fn add_cluster_args(&self, command: &mut Command, cluster: &Cluster) -> Result<()> {
+    /// The public key of the deployer of a cluster, if it exists, and whether it is encrypted.
+    ///
+    /// This does not unlock encrypted deployers.
+    pub fn get_deployer_pubkey(&self, cluster: &ClusterRef) -> Result<Option<(Pubkey, bool)>> {
+        let keypair_path = self.get_deployer_kp_path(cluster);
+        if keypair_path.exists() {
+            let pubkey = read_keypair_file(&keypair_path)
+                .map_err(|_| format_err!("could not read keypair {}", keypair_path.display()))?
+                .pubkey();
+            return Ok(Some((pubkey, false)));
+        }
+        let encrypted_kp_path = keystore_path(&keypair_path);
+        if encrypted_kp_path.exists() {
+            return Ok(Some((Keystore::read(&encrypted_kp_path)?.pubkey()?, true)));
+        }
+        Ok(None)
+    }
+
+    pub fn add_cluster_args(&self, command: &mut Command, cluster: &ClusterRef) -> Result<()> {
         let kp_path = self.get_deployer_kp_path_if_exists(cluster)?;
         command
-            .args(["--url", self.get_cluster_url(cluster)?, "--keypair"])
+            .args(self.cluster_args(cluster)?)
+            .arg("--keypair")
             .arg(kp_path);
         Ok(())
     }
 
-    pub fn exec_deployer_command<F>(&self, cluster: &Cluster, mut builder: F) -> Result<Output>
+    pub fn exec_deployer_command<F>(&self, cluster: &ClusterRef, mut builder: F) -> Result<Output>
     where
         F: FnMut(&mut Command) -> Result<()>,
     {
         let cmd = &mut new_solana_cmd();
         self.add_cluster_args(cmd, cluster)?;
         builder(cmd)?;
-        exec_command(cmd)
+        self.exec_solana_command(cluster, cmd)
     }
 
-    /// Gets the configured URL of the [Cluster] in `Goki.toml`.
-    pub fn get_cluster_url(&self, cluster: &Cluster) -> Result<&str> {
+    /// Gets the configured URL of the [ClusterRef] in `Goki.toml`.
+    pub fn get_cluster_url(&self, cluster: &ClusterRef) -> Result<&str> {
         let Workspace { cfg, .. } = self;
+        if let Some(url) = cfg
+            .clusters
+            .get(&cluster.to_string())
+            .and_then(|cluster_cfg| cluster_cfg.url.as_ref())
+        {
+            return Ok(url);
+        }
         Ok(match cluster {
-            Cluster::Debug => &cfg.rpc_endpoints.debug,
-            Cluster::Testnet => &cfg.rpc_endpoints.testnet,
-            Cluster::Mainnet => &cfg.rpc_endpoints.mainnet,
-            Cluster::Devnet => &cfg.rpc_endpoints.devnet,
-            Cluster::Localnet => &cfg.rpc_endpoints.localnet,
-            _ => panic!("cluster type not supported"),
+            ClusterRef::Builtin(Cluster::Debug) => &cfg.rpc_endpoints.debug,
+            ClusterRef::Builtin(Cluster::Testnet) => &cfg.rpc_endpoints.testnet,
+            ClusterRef::Builtin(Cluster::Mainnet) => &cfg.rpc_endpoints.mainnet,
+            ClusterRef::Builtin(Cluster::Devnet) => &cfg.rpc_endpoints.devnet,
+            ClusterRef::Builtin(Cluster::Localnet) => &cfg.rpc_endpoints.localnet,
+            ClusterRef::Builtin(Cluster::Custom(url, _)) => {
+                return Err(format_err!(
+                    "custom cluster URL {} is not supported; define a named cluster in [clusters] instead",
+                    url
+                ))
+            }
+            ClusterRef::Named(name) => {
+                return Err(format_err!(
+                    "cluster {} has no URL; set `url` in the [clusters.{}] section of Goki.toml",
+                    name,
+                    name
+                ))
+            }
         })
     }
 
-    /// The upgrader.
-    pub fn get_upgrader_wallet(&self) -> Result<String> {
-        self.cfg
-            .upgrade_authority_keypair
-            .clone()
-            .ok_or_else(|| format_err!("upgrade_authority_keypair not found in Goki.toml"))
+    /// The keypair of the upgrader of a cluster.
+    pub fn get_upgrader_wallet(&self, cluster: &ClusterRef) -> Result<String> {
+        self.check_local_signing(cluster)?;
+        match self.get_upgrade_authority(cluster)? {
+            Some(UpgradeAuthority::Keypair(path)) => Ok(path),
+            Some(UpgradeAuthority::Pubkey(pubkey)) => Err(format_err!(
+                "the upgrade authority of cluster {} is the public key {}, not a local keypair",
+                cluster,
+                pubkey
+            )),
+            None => Err(format_err!(
+                "no upgrade authority found in Goki.toml; set `upgrade_authority` in [clusters.{}] or `upgrade_authority_keypair`",
+                cluster
+            )),
+        }
     }
 
     /// New [CommandContext] using the specified cluster.
     pub fn new_cluster_context<'a, 'b>(
         &'a self,
-        cluster: &'b Cluster,
+        cluster: &'b ClusterRef,
     ) -> Result<CommandContext<'a, 'b>> {
         Ok(CommandContext {
             workspace: self,
@@ -107,17 +268,14 @@ impl Workspace {
 
 pub struct CommandContext<'a, 'b> {
     pub workspace: &'a Workspace,
-    pub cluster: &'b Cluster,
+    pub cluster: &'b ClusterRef,
 }
 
 impl<'a, 'b> CommandContext<'a, 'b> {
     fn add_cluster_args(&self, command: &mut Command, wallet: &str) -> Result<()> {
         command
-            .args([
-                "--url",
-                self.workspace.get_cluster_url(self.cluster)?,
-                "--keypair",
-            ])
+            .args(self.workspace.cluster_args(self.cluster)?)
+            .arg("--keypair")
             .arg(wallet);
         Ok(())
     }
@@ -130,7 +288,7 @@ impl<'a, 'b> CommandContext<'a, 'b> {
         let cmd = &mut new_solana_cmd();
         self.add_cluster_args(cmd, wallet)?;
         builder(cmd)?;
-        exec_command(cmd)
+        self.workspace.exec_solana_command(self.cluster, cmd)
     }
 
     /// Executes a command.
@@ -143,7 +301,7 @@ impl<'a, 'b> CommandContext<'a, 'b> {
         args.iter().for_each(|arg| {
             cmd.arg(arg.as_ref());
         });
-        exec_command(cmd)
+        self.workspace.exec_solana_command(self.cluster, cmd)
     }
 
     pub fn get_deployer_kp_path(&self) -> PathBuf {
@@ -152,8 +310,11 @@ impl<'a, 'b> CommandContext<'a, 'b> {
 
     pub fn parse_wallet_alias(&self, alias: &str) -> Result<String> {
         let result = match alias {
-            "deployer" => Ok(self.get_deployer_kp_path().display().to_string()),
-            "upgrader" => self.workspace.get_upgrader_wallet(),
+            "deployer" => self
+                .workspace
+                .get_deployer_kp_path_if_exists(self.cluster)
+                .map(|path| path.display().to_string()),
+            "upgrader" => self.workspace.get_upgrader_wallet(self.cluster),
             _ => Ok(alias.to_string()),
         };
         result.map_err(|err| format_err!("could not parse alias {}: {}", alias, err))